@@ -0,0 +1,157 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use nannou::glam::Vec2;
+
+use genetic_blobs::evolution::{
+    blob::RGB,
+    builder::{GenomeBuilder, Initializer, PopulationBuilder},
+    gene::{Crossover, Evolve, EvolveSettings, Genetic, Genome},
+    population::{compare_with, create_runner, extract_velocity, match_reference, move_to, SimpleBlobPopulation},
+    species::Speciation,
+    square::{Quadrant, Square, Transform},
+    target::Distance,
+};
+
+/// Population and genome sizes evolve is timed at, blobs and the side of a square genome
+const SIZES: [(usize, usize); 4] = [(16, 8), (32, 12), (64, 16), (128, 24)];
+
+/// A population of random genomes, so the costs differ and every operator has something to do
+fn population(blobs: usize, side: usize) -> SimpleBlobPopulation {
+    PopulationBuilder::new()
+        .size(blobs)
+        .genome(GenomeBuilder::new().dimensions(side, side))
+        .initializer(Initializer::Random)
+        .build()
+        .expect("a population of random genomes")
+}
+
+fn reference(side: usize) -> Genome<RGB> {
+    let mut reference = create_runner(side, side);
+    reference.set_quadrant(Quadrant::RightTriangularQuadrant, RGB { r: 255, g: 0, b: 0 });
+    reference
+}
+
+/// A whole generation, every iteration starts from a fresh copy of the same population
+fn evolve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evolve");
+    for (blobs, side) in SIZES {
+        let cost_function = match_reference(reference(side), Distance::Channel);
+        let settings = EvolveSettings::default();
+        let population = population(blobs, side);
+        let id = BenchmarkId::new(format!("{} blobs", blobs), format!("{}x{}", side, side));
+        group.bench_function(id, |b| {
+            b.iter_batched(
+                || (population.clone(), Speciation::new()),
+                |(mut population, mut speciation)| {
+                    SimpleBlobPopulation::evolve(&mut population, &cost_function, &settings, &mut speciation)
+                        .expect("a generation");
+                    population
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// The steps of a generation one by one, on a population of 64 blobs with 16x16 genomes
+fn operators(c: &mut Criterion) {
+    let side = 16;
+    let population = population(64, side);
+    let cost_function = match_reference(reference(side), Distance::Channel);
+    let costs = SimpleBlobPopulation::rate(&mut population.clone(), &cost_function);
+    let ranking = SimpleBlobPopulation::weight(&costs);
+    let fathers = SimpleBlobPopulation::get_fathers(&ranking, 3, 4);
+    let father_genomes: Vec<Genome<RGB>> = fathers.iter().map(|&i| population[i].genome.clone()).collect();
+    let genome = population[0].genome.clone();
+    let indices = SimpleBlobPopulation::get_indices(genome.len(), &father_genomes).expect("indices of the fathers");
+
+    let mut group = c.benchmark_group("operators");
+    group.bench_function("rate", |b| {
+        b.iter_batched(
+            || population.clone(),
+            |mut population| SimpleBlobPopulation::rate(&mut population, &cost_function),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("weight", |b| b.iter(|| SimpleBlobPopulation::weight(black_box(&costs))));
+    group.bench_function("get_fathers", |b| {
+        b.iter(|| SimpleBlobPopulation::get_fathers(black_box(&ranking), 3, 4))
+    });
+    group.bench_function("get_indices", |b| {
+        b.iter(|| SimpleBlobPopulation::get_indices(genome.len(), &father_genomes))
+    });
+    group.bench_function("get_region_indices", |b| {
+        b.iter(|| SimpleBlobPopulation::get_region_indices(&genome, &father_genomes))
+    });
+    group.bench_function("combine", |b| {
+        b.iter_batched(
+            || genome.clone(),
+            |mut child| child.combine(&father_genomes, &indices).map(|_| child),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("mutate", |b| {
+        b.iter_batched(
+            || genome.clone(),
+            |mut child| SimpleBlobPopulation::mutate(&mut child, 3).map(|_| child),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("resize", |b| {
+        b.iter_batched(
+            || genome.clone(),
+            |mut child| child.resize(side + 1, side - 1).map(|_| child),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("transform", |b| b.iter(|| genome.transformed(Transform::random())));
+    for crossover in [Crossover::Uniform, Crossover::Regions] {
+        let settings = EvolveSettings { crossover, ..Default::default() };
+        group.bench_function(BenchmarkId::new("breed", crossover.name()), |b| {
+            b.iter_batched(
+                || population.clone(),
+                |mut population| SimpleBlobPopulation::breed(&mut population, 0, &fathers, &settings).map(|_| population),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Every distance a reference can be matched with, and moving into a direction
+fn fitness(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fitness");
+    for side in [8, 16, 32] {
+        let genome = reference(side).transformed(Transform::random());
+        let target = reference(side);
+        let size = format!("{}x{}", side, side);
+        for distance in Distance::ALL {
+            group.bench_function(BenchmarkId::new(distance.name(), &size), |b| {
+                b.iter(|| compare_with(black_box(&genome), &target, distance))
+            });
+        }
+        group.bench_function(BenchmarkId::new("move_to", &size), |b| {
+            b.iter(|| move_to(black_box(&genome), Vec2::X))
+        });
+    }
+    group.finish();
+}
+
+/// extract_velocity collects the four triangles of a genome, get_quadrant alone shows what that costs
+fn velocity(c: &mut Criterion) {
+    let mut group = c.benchmark_group("velocity");
+    for side in [8, 16, 32] {
+        let genome = reference(side);
+        let size = format!("{}x{}", side, side);
+        group.bench_function(BenchmarkId::new("extract_velocity", &size), |b| {
+            b.iter(|| extract_velocity(black_box(&genome)))
+        });
+        group.bench_function(BenchmarkId::new("get_quadrant", &size), |b| {
+            b.iter(|| black_box(&genome).get_quadrant(Quadrant::RightTriangularQuadrant).len())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, evolve, operators, fitness, velocity);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+use nannou::prelude::*;
+
+use crate::evolution::population::SimpleBlobPopulation;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 8.0;
+/// zoom change per scrolled line
+const ZOOM_STEP: f32 = 0.03;
+/// amount of pixels a trackpad scrolls per line
+const PIXELS_PER_LINE: f32 = 20.0;
+/// pan distance of a key press in screen pixels
+const KEY_PAN: f32 = 30.0;
+
+/// Maps world coordinates onto the screen
+/// The screen origin is the window center, just like in nannou
+pub struct Camera {
+    pub center: Point2,
+    pub zoom: f32,
+    /// index of the blob the camera is following
+    pub follow: Option<usize>,
+    /// screen position of the last drag event
+    drag: Option<Point2>,
+    /// how far the current drag moved in total, used to tell clicks from drags
+    dragged: f32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            center: Point2::ZERO,
+            zoom: 1.0,
+            follow: None,
+            drag: None,
+            dragged: 0.0,
+        }
+    }
+
+    /// world -> screen
+    pub fn transform(&self, v: Vec2) -> Vec2 {
+        (v - self.center) * self.zoom
+    }
+
+    /// screen -> world
+    pub fn to_world(&self, v: Vec2) -> Vec2 {
+        v / self.zoom + self.center
+    }
+
+    pub fn pan(&mut self, screen_delta: Vec2) {
+        self.center -= screen_delta / self.zoom;
+        self.follow = None;
+    }
+
+    /// Changes the zoom while the world point under the cursor stays in place
+    pub fn zoom_at(&mut self, cursor: Point2, factor: f32) {
+        let anchor = self.to_world(cursor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center = anchor - cursor / self.zoom;
+    }
+
+    pub fn scroll(&mut self, delta: MouseScrollDelta, cursor: Point2) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+        };
+        let anchor = if self.follow.is_some() { Point2::ZERO } else { cursor };
+        self.zoom_at(anchor, 1.0 + ZOOM_STEP * lines);
+    }
+
+    pub fn start_drag(&mut self, at: Point2) {
+        self.drag = Some(at);
+        self.dragged = 0.0;
+    }
+
+    pub fn drag_to(&mut self, at: Point2) {
+        if let Some(last) = self.drag {
+            self.pan(at - last);
+            self.dragged += at.distance(last);
+            self.drag = Some(at);
+        }
+    }
+
+    /// Returns true if the mouse barely moved, so the drag was a click
+    pub fn end_drag(&mut self) -> bool {
+        let click = self.drag.is_some() && self.dragged < 3.0;
+        self.drag = None;
+        click
+    }
+
+    /// Returns true if the key was a camera shortcut
+    pub fn key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Left | Key::A => self.pan(Vec2::new(KEY_PAN, 0.0)),
+            Key::Right | Key::D => self.pan(Vec2::new(-KEY_PAN, 0.0)),
+            Key::Up | Key::W => self.pan(Vec2::new(0.0, -KEY_PAN)),
+            Key::Down | Key::S => self.pan(Vec2::new(0.0, KEY_PAN)),
+            Key::Plus | Key::Equals | Key::NumpadAdd => self.zoom_at(Point2::ZERO, 1.25),
+            Key::Minus | Key::NumpadSubtract => self.zoom_at(Point2::ZERO, 0.8),
+            Key::Home => *self = Camera::new(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Moves the center onto the followed blob
+    pub fn update(&mut self, population: &SimpleBlobPopulation) {
+        if let Some(blob) = self.follow.and_then(|i| population.get(i)) {
+            self.center = blob.position();
+        }
+    }
+
+    /// Centers the camera and zooms such that the bounds are visible inside the viewport
+    pub fn fit(&mut self, bounds: Option<Rect>, viewport: Rect) {
+        if let Some(bounds) = bounds {
+            self.follow = None;
+            self.center = bounds.xy();
+            let zoom = (viewport.w() / bounds.w()).min(viewport.h() / bounds.h());
+            self.zoom = (0.9 * zoom).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+}
+
+/// The area covered by a blob in world space
+pub fn blob_bounds(population: &SimpleBlobPopulation, index: usize) -> Rect {
+    let blob = &population[index];
+    Rect::from_xy_wh(blob.position(), blob.extent())
+}
+
+pub fn population_bounds(population: &SimpleBlobPopulation) -> Option<Rect> {
+    (0..population.len())
+        .map(|i| blob_bounds(population, i))
+        .reduce(union)
+}
+
+/// The smallest Rect containing both
+pub fn union(a: Rect, b: Rect) -> Rect {
+    a.stretch_to_point(b.bottom_left()).stretch_to_point(b.top_right())
+}
+
+/// Returns the index of the blob at the world position
+pub fn blob_at(population: &SimpleBlobPopulation, at: Point2) -> Option<usize> {
+    (0..population.len()).find(|&i| blob_bounds(population, i).contains(at))
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::evolution::error::EvolutionError;
+
+/// What the program was asked to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// the interactive viewer
+    Run,
+    Headless,
+    /// continue a headless run from a snapshot file
+    Resume(PathBuf),
+    /// every genome of a snapshot, or of a fresh population, as images
+    ExportImages(Option<PathBuf>),
+    /// time a few generations
+    Bench,
+    /// play back the frames of a recording
+    Replay(PathBuf),
+    /// the usage of a single command, or of all of them
+    Help(Option<String>),
+}
+
+impl Command {
+    pub const NAMES: [&'static str; 6] = ["run", "headless", "resume", "export-images", "bench", "replay"];
+}
+
+/// Every command understands these
+const COMMON: [&str; 4] = ["seed", "config", "generations", "out"];
+
+/// Flags which decide how the archipelago is built
+const WORLD: [&str; 16] = [
+    "islands", "genome", "blobs", "template", "init", "noise", "init-from", "placement", "spacing", "symmetry",
+    "encoding", "steps", "migrate-every", "migrants", "topology", "preservation",
+];
+
+/// Flags of a headless run, on top of COMMON and WORLD
+const HEADLESS: [&str; 18] = [
+    "export-every", "format", "record", "record-stride", "resolution", "zoom", "metrics", "genealogy",
+    "provenance", "target-cost", "time-budget", "stagnation", "collapse", "resize", "max-side", "crossover",
+    "transform", "snapshot",
+];
+
+/// The flags a command accepts besides COMMON
+fn flags(command: &str) -> Vec<&'static str> {
+    match command {
+        "run" => WORLD.to_vec(),
+        "headless" | "resume" => WORLD.iter().chain(HEADLESS.iter()).copied().collect(),
+        "export-images" => WORLD.iter().copied().chain(["format", "scale", "columns"]).collect(),
+        "bench" => WORLD.to_vec(),
+        "replay" => vec!["fps"],
+        _ => Vec::new(),
+    }
+}
+
+/// The usage of every command
+pub fn usage(command: Option<&str>) -> String {
+    let common = "  --seed N             repeat the random decisions of an earlier run\n\
+                  \x20 --config FILE        read flags from lines like `islands = 4`, the command line wins\n\
+                  \x20 --generations N      stop after N generations, 0 runs until another criterion stops\n\
+                  \x20 --out DIR            where images and snapshots are written\n";
+    let world = "  --islands N --genome WxH --blobs N --template random|black|runner\n\
+                 \x20 --init template|noisy|random|stratified|snapshot or a mix like template:60,random:40\n\
+                 \x20 --noise CHANCE --init-from SNAPSHOT\n\
+                 \x20 --placement rings|poisson|hex|square|random|clustered --spacing FACTOR\n\
+                 \x20 --symmetry none|bilateral|four-fold|eight-fold --encoding direct|automaton --steps N\n\
+                 \x20 --migrate-every N --migrants N\n\
+                 \x20 --topology ring|full|random\n\
+                 \x20 --preservation none|sharing|crowding|niching|speciation\n";
+    let headless = "  --export-every N --format png|ppm --snapshot FILE\n\
+                    \x20 --record DIR --record-stride N --resolution WxH --zoom Z\n\
+                    \x20 --metrics FILE.csv|FILE.jsonl --genealogy FILE.dot|FILE.json --provenance FILE.csv\n\
+                    \x20 --target-cost C --time-budget SECONDS --stagnation N --collapse SIMILARITY\n\
+                    \x20 --resize CHANCE --max-side N --crossover uniform|regions --transform CHANCE\n";
+    match command {
+        Some("run") => format!("usage: evolution run [flags]\nopens the viewer\n{}{}", common, world),
+        Some("headless") => format!(
+            "usage: evolution headless [flags]\nevolves without a window and exports the best genome\n{}{}{}",
+            common, world, headless
+        ),
+        Some("resume") => format!(
+            "usage: evolution resume <snapshot> [flags]\ncontinues a headless run from a snapshot file\n{}{}{}",
+            common, world, headless
+        ),
+        Some("export-images") => format!(
+            "usage: evolution export-images [<snapshot>] [flags]\n\
+             writes every genome and a contact sheet, of the snapshot or of a population evolved for --generations\n\
+             {}{}  --format png|ppm --scale N --columns N\n",
+            common, world
+        ),
+        Some("bench") => format!(
+            "usage: evolution bench [flags]\ntimes --generations generations, 200 by default\n{}{}",
+            common, world
+        ),
+        Some("replay") => format!(
+            "usage: evolution replay <recording> [flags]\nplays back the frames of a recording directory\n{}  --fps N\n",
+            common
+        ),
+        _ => format!(
+            "usage: evolution [command] [flags]\n\ncommands:\n\
+             \x20 run              the viewer, also used without a command\n\
+             \x20 headless         evolve without a window\n\
+             \x20 resume           continue a headless run from a snapshot\n\
+             \x20 export-images    write genomes as images\n\
+             \x20 bench            time generations\n\
+             \x20 replay           play back a recording\n\n\
+             every command understands\n{}\n\
+             evolution <command> --help shows the flags of a command\n",
+            common
+        ),
+    }
+}
+
+/// Why the command line could not be understood
+#[derive(Debug)]
+pub enum CliError {
+    UnknownCommand(String),
+    UnknownFlag { command: &'static str, flag: String },
+    MissingValue(String),
+    InvalidValue { flag: String, value: String, expected: &'static str },
+    MissingArgument { command: &'static str, argument: &'static str },
+    UnexpectedArgument(String),
+    Read { path: PathBuf, error: io::Error },
+    Write { path: PathBuf, error: io::Error },
+    ConfigLine { path: PathBuf, line: usize },
+    /// the options were valid, but the islands could not be created or evolved
+    Evolution(EvolutionError),
+    /// a headless run stopped before a termination criterion was met
+    Run(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownCommand(name) => write!(f, "unknown command `{}`, try one of {}", name, Command::NAMES.join(", ")),
+            CliError::UnknownFlag { command, flag } => write!(f, "`{}` does not understand --{}", command, flag),
+            CliError::MissingValue(flag) => write!(f, "--{} needs a value", flag),
+            CliError::InvalidValue { flag, value, expected } => {
+                write!(f, "invalid value `{}` for --{}, expected {}", value, flag, expected)
+            }
+            CliError::MissingArgument { command, argument } => write!(f, "`{}` needs a {}", command, argument),
+            CliError::UnexpectedArgument(argument) => write!(f, "unexpected argument `{}`", argument),
+            CliError::Read { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            CliError::Write { path, error } => write!(f, "failed to write {}: {}", path.display(), error),
+            CliError::ConfigLine { path, line } => {
+                write!(f, "{}:{} is neither `flag = value` nor a comment", path.display(), line)
+            }
+            CliError::Evolution(e) => write!(f, "{}", e),
+            CliError::Run(e) => write!(f, "the headless run failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<EvolutionError> for CliError {
+    fn from(e: EvolutionError) -> Self {
+        CliError::Evolution(e)
+    }
+}
+
+/// The flags of a command, from the config file first and from the command line second
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    values: Vec<(String, String)>,
+}
+
+impl Options {
+    /// The last value of a flag, without the leading --
+    pub fn text(&self, flag: &str) -> Option<&str> {
+        self.values.iter().rev().find(|(f, _)| f == flag).map(|(_, v)| v.as_str())
+    }
+
+    /// Parses the value of a flag, expected describes a valid value for the error
+    pub fn value<T: FromStr>(&self, flag: &str, expected: &'static str) -> Result<Option<T>, CliError> {
+        self.text(flag)
+            .map(|value| {
+                value.parse().map_err(|_| CliError::InvalidValue {
+                    flag: flag.to_string(),
+                    value: value.to_string(),
+                    expected,
+                })
+            })
+            .transpose()
+    }
+
+    /// Finds the value of a flag among a few named choices
+    pub fn choice<T, I>(&self, flag: &str, choices: I, name: fn(&T) -> &str, expected: &'static str) -> Result<Option<T>, CliError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.text(flag)
+            .map(|value| {
+                choices.into_iter().find(|c| name(c) == value).ok_or_else(|| CliError::InvalidValue {
+                    flag: flag.to_string(),
+                    value: value.to_string(),
+                    expected,
+                })
+            })
+            .transpose()
+    }
+
+    /// WxH, e.g. 12x12, both sides at least 1
+    pub fn dimensions(&self, flag: &str) -> Result<Option<(usize, usize)>, CliError> {
+        self.text(flag)
+            .map(|value| {
+                value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|&(w, h): &(usize, usize)| w > 0 && h > 0)
+                    .ok_or_else(|| CliError::InvalidValue {
+                        flag: flag.to_string(),
+                        value: value.to_string(),
+                        expected: "WIDTHxHEIGHT",
+                    })
+            })
+            .transpose()
+    }
+
+    pub fn seed(&self) -> Result<Option<u64>, CliError> {
+        self.value("seed", "a whole number")
+    }
+
+    pub fn generations(&self) -> Result<Option<usize>, CliError> {
+        self.value("generations", "a whole number")
+    }
+
+    pub fn out(&self) -> Option<PathBuf> {
+        self.text("out").map(PathBuf::from)
+    }
+}
+
+/// Reads the command and its flags, args does not contain the program name
+pub fn parse(args: &[String]) -> Result<(Command, Options), CliError> {
+    let (name, rest) = match args.first().map(|a| a.as_str()) {
+        None => ("run", &args[..0]),
+        Some("help" | "--help" | "-h") => return Ok((Command::Help(args.get(1).cloned()), Options::default())),
+        // flags without a command belong to the viewer
+        Some(first) if first.starts_with("--") => ("run", args),
+        Some(first) => match Command::NAMES.iter().find(|&&n| n == first) {
+            Some(name) => (*name, &args[1..]),
+            None => return Err(CliError::UnknownCommand(first.to_string())),
+        },
+    };
+    let allowed: Vec<&str> = COMMON.iter().copied().chain(flags(name)).collect();
+    let command_name = Command::NAMES.iter().find(|&&n| n == name).copied().unwrap_or("run");
+
+    let mut command_line = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok((Command::Help(Some(name.to_string())), Options::default()));
+        }
+        match arg.strip_prefix("--") {
+            Some(flag) => {
+                if !allowed.contains(&flag) {
+                    return Err(CliError::UnknownFlag { command: command_name, flag: flag.to_string() });
+                }
+                let value = args.next().ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+                command_line.push((flag.to_string(), value.clone()));
+            }
+            None => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut options = Options { values: command_line };
+    if let Some(path) = options.text("config").map(PathBuf::from) {
+        let mut values = read_config(&path, &allowed, command_name)?;
+        values.append(&mut options.values);
+        options.values = values;
+    }
+
+    let mut positional = positional.into_iter();
+    let mut required = |argument| {
+        positional
+            .next()
+            .ok_or(CliError::MissingArgument { command: command_name, argument })
+    };
+    let command = match name {
+        "headless" => Command::Headless,
+        "resume" => Command::Resume(required("snapshot file")?),
+        "export-images" => Command::ExportImages(positional.next()),
+        "bench" => Command::Bench,
+        "replay" => Command::Replay(required("recording directory")?),
+        _ => Command::Run,
+    };
+    if let Some(extra) = positional.next() {
+        return Err(CliError::UnexpectedArgument(extra.display().to_string()));
+    }
+    Ok((command, options))
+}
+
+/// Lines like `flag = value` or `flag value`, # starts a comment
+fn read_config(path: &Path, allowed: &[&str], command: &'static str) -> Result<Vec<(String, String)>, CliError> {
+    let content = fs::read_to_string(path).map_err(|error| CliError::Read { path: path.to_path_buf(), error })?;
+    let mut values = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (flag, value) = line
+            .split_once('=')
+            .or_else(|| line.split_once(char::is_whitespace))
+            .map(|(f, v)| (f.trim().trim_start_matches("--"), v.trim()))
+            .filter(|(f, v)| !f.is_empty() && !v.is_empty())
+            .ok_or(CliError::ConfigLine { path: path.to_path_buf(), line: number + 1 })?;
+        if !allowed.contains(&flag) || flag == "config" {
+            return Err(CliError::UnknownFlag { command, flag: flag.to_string() });
+        }
+        values.push((flag.to_string(), value.to_string()));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::diversity::Preservation;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn config(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("evolution_{}_{}.conf", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn flags_without_a_command_start_the_viewer() {
+        let (command, options) = parse(&args("--blobs 8")).unwrap();
+        assert_eq!(command, Command::Run);
+        assert_eq!(options.text("blobs"), Some("8"));
+        assert_eq!(parse(&[]).unwrap().0, Command::Run);
+        assert_eq!(parse(&args("headless --help")).unwrap().0, Command::Help(Some("headless".to_string())));
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_flags() {
+        assert!(matches!(parse(&args("evolve")), Err(CliError::UnknownCommand(c)) if c == "evolve"));
+        assert!(matches!(
+            parse(&args("bench --suite all")),
+            Err(CliError::UnknownFlag { command: "bench", flag }) if flag == "suite"
+        ));
+        // headless flags are unknown to the viewer
+        assert!(matches!(
+            parse(&args("--metrics m.csv")),
+            Err(CliError::UnknownFlag { command: "run", flag }) if flag == "metrics"
+        ));
+    }
+
+    #[test]
+    fn a_flag_needs_a_value() {
+        assert!(matches!(parse(&args("headless --generations")), Err(CliError::MissingValue(f)) if f == "generations"));
+    }
+
+    #[test]
+    fn the_command_line_overrides_the_config() {
+        let path = config("precedence", "# a comment\nblobs = 8\ngenerations 50 # a trailing comment\n--seed=3\n");
+        let line = format!("headless --blobs 16 --config {}", path.display());
+        let (_, options) = parse(&args(&line)).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(options.text("blobs"), Some("16"));
+        assert_eq!(options.text("generations"), Some("50"));
+        assert_eq!(options.text("seed"), Some("3"));
+    }
+
+    #[test]
+    fn rejects_invalid_config_lines() {
+        for (name, content) in [("line", "blobs\n"), ("flag", "metrics = m.csv\n"), ("nested", "config other.conf\n")] {
+            let path = config(name, content);
+            let result = parse(&args(&format!("--config {}", path.display())));
+            fs::remove_file(&path).unwrap();
+            match (name, result) {
+                ("line", Err(CliError::ConfigLine { line: 1, .. })) => {}
+                ("flag" | "nested", Err(CliError::UnknownFlag { command: "run", .. })) => {}
+                (name, result) => panic!("{}: unexpected {:?}", name, result),
+            }
+        }
+        assert!(matches!(parse(&args("--config /nonexistent/evolution.conf")), Err(CliError::Read { .. })));
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let (command, _) = parse(&args("resume out/snapshot.txt --generations 5")).unwrap();
+        assert_eq!(command, Command::Resume(PathBuf::from("out/snapshot.txt")));
+        assert!(matches!(
+            parse(&args("resume")),
+            Err(CliError::MissingArgument { command: "resume", .. })
+        ));
+        assert_eq!(parse(&args("export-images")).unwrap().0, Command::ExportImages(None));
+        assert!(matches!(parse(&args("headless extra")), Err(CliError::UnexpectedArgument(a)) if a == "extra"));
+        assert!(matches!(parse(&args("replay a b")), Err(CliError::UnexpectedArgument(a)) if a == "b"));
+    }
+
+    #[test]
+    fn choices_match_whole_names() {
+        let (_, options) = parse(&args("headless --preservation sharing")).unwrap();
+        let preservation = options.choice("preservation", Preservation::all(), Preservation::flag, "");
+        assert!(matches!(preservation, Ok(Some(Preservation::Sharing { .. }))));
+        let (_, options) = parse(&args("headless --preservation shar")).unwrap();
+        let preservation = options.choice("preservation", Preservation::all(), Preservation::flag, "");
+        assert!(matches!(preservation, Err(CliError::InvalidValue { .. })));
+    }
+}
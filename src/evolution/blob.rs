@@ -1,18 +1,23 @@
-use nannou::color::Rgb;
-use nannou::geom::{pt2, Point2, Vec2};
-use nannou::Draw;
+use std::collections::BTreeMap;
+
+use nannou::color::{hsl, Rgb};
+use nannou::geom::{pt2, Point2, Rect, Vec2};
+use nannou::{wgpu, Draw};
 /// external crate
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::{
-    Model, Nannou,
-    util::Create,
+    Nannou, View,
+    util::{rng, Create},
     evolution::{
-        gene::{Creature, Genome, Compare},
-        square::{Square, Quadrant},
+        development::Encoding,
+        error::EvolutionError,
+        gene::{Creature, Genome, Compare, Provenance},
+        lineage::Lineage,
+        square::{Region, Square, Quadrant},
     }};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -23,22 +28,24 @@ impl RGB {
     pub fn as_color(&self) -> (u8, u8, u8) {
         (self.r, self.g, self.b)
     }
+
+    /// The amount of classes of bucket
+    pub const BUCKETS: usize = 64;
+
+    /// Sorts the color into one of 4 * 4 * 4 coarse classes
+    pub fn bucket(&self) -> usize {
+        (self.r as usize / 64) * 16 + (self.g as usize / 64) * 4 + self.b as usize / 64
+    }
 }
 
 impl Create for RGB {
-    type Params = ();
-
-    fn create() -> Self {
-        let mut rng = thread_rng();
-        RGB {
+    fn create() -> Result<Self, EvolutionError> {
+        let mut rng = rng();
+        Ok(RGB {
             r: rng.gen(),
             g: rng.gen(),
             b: rng.gen(),
-        }
-    }
-
-    fn create_like(_params: Option<Self::Params>) -> Self {
-        Self::create()
+        })
     }
 }
 
@@ -52,33 +59,101 @@ impl Compare for RGB {
     }
 }
 
+/// The ways a Blob can be drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    Rect,
+    SimpleRect,
+    Circle,
+    SimpleCircle,
+    Debug,
+    /// a single textured quad per blob, see simulation::atlas
+    Texture,
+}
+
+impl DrawMode {
+    pub const ALL: [DrawMode; 6] = [
+        DrawMode::Rect,
+        DrawMode::SimpleRect,
+        DrawMode::Circle,
+        DrawMode::SimpleCircle,
+        DrawMode::Debug,
+        DrawMode::Texture,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DrawMode::Rect => "rect",
+            DrawMode::SimpleRect => "simple rect",
+            DrawMode::Circle => "circle",
+            DrawMode::SimpleCircle => "simple circle",
+            DrawMode::Debug => "debug",
+            DrawMode::Texture => "texture",
+        }
+    }
+}
+
+/// Extra information drawn on top of the genes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    None,
+    /// where every gene of the current generation came from
+    Provenance,
+    /// how often a gene position was not inherited from the mother
+    Churn,
+    /// an outline in the color of the species, see evolution::species
+    Species,
+}
+
+impl Overlay {
+    pub const ALL: [Overlay; 4] = [Overlay::None, Overlay::Provenance, Overlay::Churn, Overlay::Species];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Overlay::None => "none",
+            Overlay::Provenance => "provenance",
+            Overlay::Churn => "churn",
+            Overlay::Species => "species",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Blob {
     pub genome: Genome<RGB>,
     nannou_size: f32,
     nannou_position: Point2,
     pub velocity: Vec2,
+    pub lineage: Lineage,
+    /// where the genes came from during the last generation, empty for founders
+    pub provenance: Vec<Provenance>,
+    /// what the genome grew into, None while every gene is drawn as it is
+    phenotype: Option<Genome<RGB>>,
 }
 
 impl Creature<RGB> for Blob {
     fn extract_genome(&mut self) -> &mut Genome<RGB> {
         &mut self.genome
     }
+
+    fn extract_lineage(&mut self) -> &mut Lineage {
+        &mut self.lineage
+    }
 }
 
 impl Nannou for Blob {
-    fn draw(&self, draw: &Draw, model: &Model) {
-        let position = model.transform(self.nannou_position);
-        let size = self.nannou_size * model.zoom;
-        let mode = 0;
-
-        match mode {
-            0 => self.draw_rect(draw, position, size),
-            1 => self.draw_simple_rect(draw, position, size),
-            2 => self.draw_circle(draw),
-            3 => self.draw_simple_circle(draw),
-            4 => self.draw_debug(draw, position, size),
-            _ => panic!("This drawing mode does not exist!"),
+    fn draw(&self, draw: &Draw, view: &View) {
+        let position = view.transform(self.nannou_position);
+        let size = self.nannou_size * view.camera.zoom;
+
+        match view.draw_mode {
+            DrawMode::Rect => self.draw_rect(draw, position, size),
+            DrawMode::SimpleRect => self.draw_simple_rect(draw, position, size),
+            DrawMode::Circle => self.draw_circle(draw, position, size),
+            DrawMode::SimpleCircle => self.draw_simple_circle(draw, position, size),
+            DrawMode::Debug => self.draw_debug(draw, position, size),
+            // without an atlas there is nothing to sample from
+            DrawMode::Texture => self.draw_rect(draw, position, size),
         }
     }
 
@@ -94,110 +169,212 @@ impl Blob {
             nannou_size,
             nannou_position,
             velocity: Vec2::ZERO,
+            lineage: Lineage::default(),
+            provenance: Vec::new(),
+            phenotype: None,
         }
     }
 
+    /// The grid which is drawn, either the genome itself or what it grew into
+    pub fn phenotype(&self) -> &Genome<RGB> {
+        self.phenotype.as_ref().unwrap_or(&self.genome)
+    }
+
+    /// Grows the phenotype again, call it whenever the genome or the encoding changed
+    pub fn develop(&mut self, encoding: &Encoding) {
+        self.phenotype = encoding.develop(&self.genome);
+    }
+
+    pub fn position(&self) -> Point2 {
+        self.nannou_position
+    }
+
+    /// The size of a single gene when drawn
+    pub fn size(&self) -> f32 {
+        self.nannou_size
+    }
+
+    /// The extent of the whole genome when drawn
+    pub fn extent(&self) -> Vec2 {
+        let (width, height) = self.phenotype().dimensions();
+        Vec2::new(width as f32, height as f32) * self.nannou_size
+    }
+
+    /// The center of the bottom left gene when the genome is drawn around at
+    fn bottom_left(&self, at: Vec2, size: f32) -> (f32, f32) {
+        let (width, height) = self.phenotype().dimensions();
+        (
+            at.x - (width as f32 / 2.) * size + size / 2.,
+            at.y - (height as f32 / 2.) * size + size / 2.,
+        )
+    }
+
+    /// The radius of the largest circle of gene centers fitting into the genome
+    fn radius(&self, size: f32) -> f32 {
+        let (width, height) = self.phenotype().dimensions();
+        (width.min(height) as f32 / 2.) * size - size / 2.
+    }
+
     pub fn draw_rect(&self, draw: &Draw, at: Vec2, size: f32) {
-        let width = (self.genome.len() as f32).sqrt() as usize;
-        let offset = (width as f32 / 2.) * size - size / 2.;
-        let bottom_left = (at.x - offset, at.y - offset);
-        (0..self.genome.len()).for_each(|i| {
+        let genome = self.phenotype();
+        let bottom_left = self.bottom_left(at, size);
+        let (width, height) = genome.dimensions();
+        (0..width * height).for_each(|i| {
             let temp = i / width;
             let y = bottom_left.1 + (temp as f32) * size;
             let x = bottom_left.0 + (i % width as usize) as f32 * size;
             draw.rect()
                 .x_y(x, y)
                 .w_h(size, size)
-                .color(Rgb::from_components(self.genome[i].as_color()));
+                .color(Rgb::from_components(genome.get((i % width, temp)).as_color()));
         });
     }
 
     pub fn draw_debug(&self, draw: &Draw, at: Vec2, size: f32) {
-        let width = (self.genome.len() as f32).sqrt() as usize;
-        let offset = (width as f32 / 2.) * size - size / 2.;
-        let bottom_left = (at.x - offset, at.y - offset);
-        let quadrant = self.genome.get_quadrant(Quadrant::TopTriangularQuadrant);
-        (0..self.genome.len()).for_each(|i| {
+        let genome = self.phenotype();
+        let bottom_left = self.bottom_left(at, size);
+        let width = genome.width();
+        let quadrant = genome.region_indices(&Region::Quadrant(Quadrant::TopTriangularQuadrant));
+        quadrant.iter().for_each(|&i| {
             let temp = i / width;
             let y = bottom_left.1 + (temp as f32) * size;
             let x = bottom_left.0 + (i % width as usize) as f32 * size;
-            let color = &self.genome[i];
-            if quadrant.iter().any(|&x| std::ptr::eq(x, color)) {
-                draw.rect()
+            draw.rect()
                 .x_y(x, y)
                 .w_h(size, size)
-                .color(Rgb::from_components(color.as_color()));
-            }
+                .color(Rgb::from_components(genome.get((i % width, temp)).as_color()));
         });
     }
 
     pub fn draw_simple_rect(&self, draw: &Draw, at: Vec2, size: f32) {
-        let width = (self.genome.len() as f32).sqrt() as usize;
-        let offset = (width as f32 / 2.) * size - size / 2.;
-        let bottom_left = (at.x - offset, at.y - offset);
+        let genome = self.phenotype();
+        let bottom_left = self.bottom_left(at, size);
+        let (width, height) = genome.dimensions();
 
-        (0..width).for_each(|row| {
+        (0..height).for_each(|row| {
             let points = (0..width).map(|column| {
-                let index = row * width + column;
                 let x = bottom_left.0 + column as f32 * size;
                 let y = bottom_left.1 + row as f32 * size;
                 (
                     pt2(x, y),
-                    Rgb::from_components(self.genome[index].as_color()),
+                    Rgb::from_components(genome.get((column, row)).as_color()),
                 )
             });
             draw.polyline().weight(size).points_colored(points);
         })
     }
 
-    pub fn draw_circle(&self, draw: &Draw) {
-        let width = (self.genome.len() as f32).sqrt() as usize;
-        let offset = (width as f32 / 2.) * self.nannou_size - self.nannou_size / 2.;
-        let bottom_left = (
-            self.nannou_position.x - offset,
-            self.nannou_position.y - offset,
-        );
-        (0..self.genome.len()).for_each(|i| {
+    pub fn draw_circle(&self, draw: &Draw, at: Vec2, size: f32) {
+        let genome = self.phenotype();
+        let bottom_left = self.bottom_left(at, size);
+        let (width, height) = genome.dimensions();
+        let radius = self.radius(size);
+        (0..width * height).for_each(|i| {
             let temp = i / width;
-            let y = bottom_left.1 + (temp as f32) * self.nannou_size;
-            let x = bottom_left.0 + (i % width as usize) as f32 * self.nannou_size;
-            let dist = self.nannou_position.distance(Vec2::new(x, y));
+            let y = bottom_left.1 + (temp as f32) * size;
+            let x = bottom_left.0 + (i % width as usize) as f32 * size;
+            let dist = at.distance(Vec2::new(x, y));
 
-            if dist <= offset {
+            if dist <= radius {
                 draw.rect()
                     .x_y(x, y)
-                    .w_h(self.nannou_size, self.nannou_size)
-                    .color(Rgb::from_components(self.genome[i].as_color()));
+                    .w_h(size, size)
+                    .color(Rgb::from_components(genome.get((i % width, temp)).as_color()));
             }
         });
     }
 
-    pub fn draw_simple_circle(&self, draw: &Draw) {
-        let width = (self.genome.len() as f32).sqrt() as usize;
-        let offset = (width as f32 / 2.) * self.nannou_size - self.nannou_size / 2.;
-        let bottom_left = (
-            self.nannou_position.x - offset,
-            self.nannou_position.y - offset,
-        );
+    pub fn draw_simple_circle(&self, draw: &Draw, at: Vec2, size: f32) {
+        let genome = self.phenotype();
+        let bottom_left = self.bottom_left(at, size);
+        let (width, height) = genome.dimensions();
+        let radius = self.radius(size);
 
-        (0..width).for_each(|row| {
+        (0..height).for_each(|row| {
             let mut points = Vec::new();
             for column in 0..width {
-                let index = row * width + column;
-                let x = bottom_left.0 + column as f32 * self.nannou_size;
-                let y = bottom_left.1 + row as f32 * self.nannou_size;
-                let dist = self.nannou_position.distance(Vec2::new(x, y));
+                let x = bottom_left.0 + column as f32 * size;
+                let y = bottom_left.1 + row as f32 * size;
+                let dist = at.distance(Vec2::new(x, y));
 
-                if dist <= offset {
+                if dist <= radius {
                     points.push((
                         pt2(x, y),
-                        Rgb::from_components(self.genome[index].as_color()),
+                        Rgb::from_components(genome.get((column, row)).as_color()),
                     ))
                 }
             }
             draw.polyline()
-                .weight(self.nannou_size)
+                .weight(size)
                 .points_colored(points);
         })
     }
+
+    /// Tints every gene according to its Provenance
+    /// mothers are left alone, fathers are blue and mutations magenta
+    pub fn draw_provenance(&self, draw: &Draw, at: Vec2, size: f32) {
+        self.draw_tint(draw, at, size, |x, y| match self.provenance.get(self.genome.gene_index(x, y)) {
+            Some(Provenance::Father(_)) => Some((0.2, 0.4, 1.0, 0.6)),
+            Some(Provenance::Mutated) => Some((1.0, 0.0, 1.0, 0.8)),
+            _ => None,
+        });
+    }
+
+    /// Tints every gene red according to the heat of its position in [0, 1]
+    pub fn draw_heat(&self, draw: &Draw, at: Vec2, size: f32, heat: &BTreeMap<(usize, usize), f32>) {
+        self.draw_tint(draw, at, size, |x, y| heat.get(&(x, y)).map(|&h| (1.0, 0.1, 0.1, 0.85 * h.clamp(0.0, 1.0))));
+    }
+
+    /// tint gets the x and y of a gene, as in Square::get
+    /// Developed genomes are not drawn gene by gene, so they are not tinted
+    fn draw_tint<F>(&self, draw: &Draw, at: Vec2, size: f32, tint: F)
+    where
+        F: Fn(usize, usize) -> Option<(f32, f32, f32, f32)>,
+    {
+        if self.phenotype.is_some() {
+            return;
+        }
+        let bottom_left = self.bottom_left(at, size);
+        let (width, height) = self.genome.dimensions();
+        (0..width * height).for_each(|i| {
+            if let Some((r, g, b, a)) = tint(i % width, i / width) {
+                let y = bottom_left.1 + (i / width) as f32 * size;
+                let x = bottom_left.0 + (i % width) as f32 * size;
+                draw.rect().x_y(x, y).w_h(size, size).rgba(r, g, b, a);
+            }
+        });
+    }
+
+    /// Outlines the whole genome in a color with the given hue
+    pub fn draw_outline(&self, draw: &Draw, at: Vec2, size: f32, hue: f32) {
+        let (width, height) = self.phenotype().dimensions();
+        draw.rect()
+            .xy(at)
+            .w_h(width as f32 * size, height as f32 * size)
+            .no_fill()
+            .stroke(hsl(hue, 0.8, 0.6))
+            .stroke_weight(2.0);
+    }
+
+    /// One dot per rating point above the genome
+    pub fn draw_rating(&self, draw: &Draw, at: Vec2, size: f32, rating: u8) {
+        let extent = self.extent() / self.nannou_size * size;
+        let radius = (size * 1.5).max(2.0);
+        for k in 0..rating {
+            let x = at.x + (k as f32 - (rating - 1) as f32 / 2.) * radius * 3.;
+            draw.ellipse()
+                .x_y(x, at.y + extent.y / 2. + radius * 2.)
+                .radius(radius)
+                .rgb(1.0, 0.85, 0.1);
+        }
+    }
+
+    /// Draws the whole genome as one quad, area is the part of the atlas holding this genome
+    pub fn draw_texture(&self, draw: &Draw, at: Vec2, size: f32, texture: &wgpu::Texture, area: Rect) {
+        let (width, height) = self.phenotype().dimensions();
+        draw.texture(texture)
+            .xy(at)
+            .w_h(width as f32 * size, height as f32 * size)
+            .area(area);
+    }
 }
@@ -0,0 +1,300 @@
+use nannou::glam::Vec2;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    evolution::{
+        blob::{Blob, RGB},
+        error::EvolutionError,
+        gene::{Genetic, Genome},
+        placement::{blob_spacing, Placement},
+        population::{create_black, create_runner, SimpleBlobPopulation},
+        square::Symmetry,
+    },
+    util::{rng, Create},
+};
+
+/// What the genes of a new genome start as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// every gene is a random color
+    Random,
+    Black,
+    /// a red bottom row on black, see population::create_runner
+    Runner,
+}
+
+impl Template {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Template::Random => "random",
+            Template::Black => "black",
+            Template::Runner => "runner",
+        }
+    }
+}
+
+/// Builds a single genome from a template
+/// GenomeBuilder::new().dimensions(16, 8).symmetry(Symmetry::Bilateral).template(Template::Black).build()
+#[derive(Debug, Clone)]
+pub struct GenomeBuilder {
+    width: usize,
+    height: usize,
+    symmetry: Symmetry,
+    template: Template,
+}
+
+impl Default for GenomeBuilder {
+    /// A 12x12 runner without symmetry, like the genomes the viewer starts with
+    fn default() -> Self {
+        GenomeBuilder {
+            width: 12,
+            height: 12,
+            symmetry: Symmetry::None,
+            template: Template::Runner,
+        }
+    }
+}
+
+impl GenomeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dimensions(mut self, width: usize, height: usize) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn template(mut self, template: Template) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Templates are drawn on the whole grid and folded into the symmetry afterwards
+    pub fn build(&self) -> Result<Genome<RGB>, EvolutionError> {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return Err(EvolutionError::EmptyGenome { width, height });
+        }
+        let genome = match &self.template {
+            Template::Random => {
+                let genes = (0..self.symmetry.domain_size(width, height))
+                    .map(|_| RGB::create())
+                    .collect::<Result<_, _>>()?;
+                return Genome::try_symmetric(genes, width, height, self.symmetry);
+            }
+            Template::Black => create_black(width, height),
+            Template::Runner => create_runner(width, height),
+        };
+        Ok(genome.folded(self.symmetry))
+    }
+
+    /// A copy of another genome, resized around its center and folded into the symmetry
+    pub fn build_from(&self, genome: &Genome<RGB>) -> Result<Genome<RGB>, EvolutionError> {
+        let mut genome = genome.expanded().into_owned();
+        if genome.dimensions() != (self.width, self.height) {
+            genome.resize(self.width, self.height)?;
+        }
+        Ok(genome.folded(self.symmetry))
+    }
+
+    /// Latin hypercube sampling, across the *amount* genomes every channel of every gene
+    /// takes a value out of each of *amount* equal strata of 0..=255
+    pub fn build_stratified(&self, amount: usize) -> Result<Vec<Genome<RGB>>, EvolutionError> {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 {
+            return Err(EvolutionError::EmptyGenome { width, height });
+        }
+        let genes = self.symmetry.domain_size(width, height);
+        let mut rng = rng();
+        let mut genomes = vec![Vec::with_capacity(genes); amount];
+        for _ in 0..genes {
+            // one shuffled list of strata per channel, genome i takes the i-th stratum of each
+            let strata: [Vec<u8>; 3] = [(); 3].map(|_| {
+                let mut strata: Vec<u8> = (0..amount)
+                    .map(|stratum| ((stratum as f32 + rng.gen::<f32>()) * 256.0 / amount as f32).min(255.0) as u8)
+                    .collect();
+                strata.shuffle(&mut rng);
+                strata
+            });
+            for (i, genome) in genomes.iter_mut().enumerate() {
+                genome.push(RGB { r: strata[0][i], g: strata[1][i], b: strata[2][i] });
+            }
+        }
+        genomes
+            .into_iter()
+            .map(|genes| Genome::try_symmetric(genes, width, height, self.symmetry))
+            .collect()
+    }
+}
+
+/// How the genomes of a new population are chosen
+#[derive(Debug, Clone)]
+pub enum Initializer {
+    /// every blob starts as the template of the GenomeBuilder
+    Template,
+    /// the template with this share of its genes replaced by random colors
+    Noisy(f32),
+    /// every gene is a random color
+    Random,
+    /// random colors which cover the whole color range evenly, see GenomeBuilder::build_stratified
+    Stratified,
+    /// copies of these genomes, e.g. out of a snapshot, cycled over the population
+    Genomes(Vec<Genome<RGB>>),
+    /// initializers with their share of the population in percent, the shares are normalised
+    Mix(Vec<(Initializer, f32)>),
+}
+
+impl Initializer {
+    /// *amount* genomes, a mix is shuffled so its parts end up all over the population
+    pub fn genomes(&self, genome: &GenomeBuilder, amount: usize) -> Result<Vec<Genome<RGB>>, EvolutionError> {
+        match self {
+            Initializer::Template => (0..amount).map(|_| genome.build()).collect(),
+            Initializer::Noisy(noise) => {
+                if !(0.0..=1.0).contains(noise) {
+                    return Err(EvolutionError::InvalidSetting { setting: "noise", expected: "between 0 and 1" });
+                }
+                let mut rng = rng();
+                (0..amount)
+                    .map(|_| {
+                        let mut template = genome.build()?;
+                        for gene in template.iter_mut() {
+                            if rng.gen::<f32>() < *noise {
+                                *gene = RGB::create()?;
+                            }
+                        }
+                        Ok(template)
+                    })
+                    .collect()
+            }
+            Initializer::Random => {
+                let random = genome.clone().template(Template::Random);
+                (0..amount).map(|_| random.build()).collect()
+            }
+            Initializer::Stratified => genome.build_stratified(amount),
+            Initializer::Genomes(genomes) => {
+                if genomes.is_empty() {
+                    return Err(EvolutionError::EmptyPopulation);
+                }
+                genomes.iter().cycle().take(amount).map(|g| genome.build_from(g)).collect()
+            }
+            Initializer::Mix(parts) => {
+                let total: f32 = parts.iter().map(|(_, share)| share).sum();
+                if parts.iter().any(|(_, share)| !(*share >= 0.0)) || !(total > 0.0) || !total.is_finite() {
+                    return Err(EvolutionError::InvalidSetting {
+                        setting: "mix",
+                        expected: "shares which are not negative and add up to more than 0",
+                    });
+                }
+                let mut genomes = Vec::with_capacity(amount);
+                let mut share = 0.0;
+                for (initializer, part) in parts {
+                    // rounding the running total keeps the parts adding up to amount
+                    let start = (share / total * amount as f32).round() as usize;
+                    share += part;
+                    let end = (share / total * amount as f32).round() as usize;
+                    genomes.extend(initializer.genomes(genome, end.saturating_sub(start))?);
+                }
+                genomes.shuffle(&mut rng());
+                Ok(genomes)
+            }
+        }
+    }
+}
+
+/// Builds the blobs of a population, the initializer decides the genomes the GenomeBuilder provides
+/// The placement spreads the blobs around offset, at least spacing times as far apart as they need not to overlap
+#[derive(Debug, Clone)]
+pub struct PopulationBuilder {
+    size: usize,
+    /// the size of a single gene in world space
+    blob_size: f32,
+    offset: Vec2,
+    placement: Placement,
+    spacing: f32,
+    genome: GenomeBuilder,
+    initializer: Initializer,
+}
+
+impl Default for PopulationBuilder {
+    fn default() -> Self {
+        PopulationBuilder {
+            size: 32,
+            blob_size: 3.0,
+            offset: Vec2::ZERO,
+            placement: Placement::Rings,
+            spacing: 1.0,
+            genome: GenomeBuilder::default(),
+            initializer: Initializer::Template,
+        }
+    }
+}
+
+impl PopulationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The amount of blobs
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The center of the population
+    pub fn offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// A factor on the distance between blobs, 1 lets them touch
+    pub fn spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn genome(mut self, genome: GenomeBuilder) -> Self {
+        self.genome = genome;
+        self
+    }
+
+    pub fn initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    pub fn build(&self) -> Result<SimpleBlobPopulation, EvolutionError> {
+        if self.size == 0 {
+            return Err(EvolutionError::EmptyPopulation);
+        }
+        if !(self.spacing >= 1.0) || !self.spacing.is_finite() {
+            return Err(EvolutionError::InvalidSetting { setting: "spacing", expected: "at least 1" });
+        }
+        let genomes = self.initializer.genomes(&self.genome, self.size)?;
+        let spacing = blob_spacing(self.genome.width, self.genome.height, self.blob_size) * self.spacing;
+        let positions = self.placement.positions(&genomes, spacing);
+        if positions.len() != self.size {
+            return Err(EvolutionError::Placement {
+                placement: self.placement.name(),
+                placed: positions.len(),
+                blobs: self.size,
+            });
+        }
+        Ok(genomes
+            .into_iter()
+            .zip(positions)
+            .map(|(genome, position)| Blob::new(genome, self.blob_size, position + self.offset))
+            .collect())
+    }
+}
@@ -0,0 +1,107 @@
+use std::rc::Rc;
+
+use crate::evolution::{
+    blob::RGB,
+    gene::{CostFunction, Genome},
+};
+
+/// The amount of states of a cell of the automaton, every state has a color
+const STATES: usize = 4;
+/// Neighbour counts run from 0 to 8
+const COUNTS: usize = 9;
+/// Genes holding the rule table, 2 bits per entry and 3 bytes per gene
+const RULE_GENES: usize = (STATES * COUNTS + 11) / 12;
+/// The gene holding the seed, 2 bits for each of the 3 * 3 cells around the center
+const SEED_GENE: usize = STATES + RULE_GENES;
+
+/// Width and height of a freshly created automaton genome, enough to hold palette, rules and seed
+pub const AUTOMATON_GENOME: (usize, usize) = (4, 2);
+
+/// How a genome grows into the grid which is drawn and rated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// every gene is one cell of the grid
+    Direct,
+    /// the genes are the palette, rules and seed of a cellular automaton which grows a width * height grid
+    /// Genes are read in stored order, missing genes count as black
+    Automaton { width: usize, height: usize, steps: usize },
+}
+
+impl Encoding {
+    /// An automaton which runs long enough for the seed to reach the border
+    pub fn automaton(width: usize, height: usize) -> Encoding {
+        Encoding::Automaton { width, height, steps: width.max(height) / 2 }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Direct => "direct",
+            Encoding::Automaton { .. } => "automaton",
+        }
+    }
+
+    /// The grown grid, None if the genome already is the grid
+    pub fn develop(&self, genome: &Genome<RGB>) -> Option<Genome<RGB>> {
+        match *self {
+            Encoding::Direct => None,
+            Encoding::Automaton { width, height, steps } => Some(grow(genome, width, height, steps)),
+        }
+    }
+
+    /// A cost function for genomes which rates what they grow into
+    pub fn developed(&self, cost_function: CostFunction<Genome<RGB>>) -> CostFunction<Genome<RGB>> {
+        match *self {
+            Encoding::Direct => cost_function,
+            encoding => Rc::new(move |genome| match encoding.develop(genome) {
+                Some(grown) => cost_function(&grown),
+                None => cost_function(genome),
+            }),
+        }
+    }
+}
+
+/// Runs an outer totalistic automaton: the next state of a cell depends on its own state
+/// and on how many of its 8 neighbours are not in state 0, cells outside the grid are in state 0
+fn grow(genome: &Genome<RGB>, width: usize, height: usize, steps: usize) -> Genome<RGB> {
+    let gene = |i: usize| genome.get(i).copied().unwrap_or(RGB { r: 0, g: 0, b: 0 });
+    let rules: Vec<u8> = (STATES..SEED_GENE)
+        .flat_map(|i| {
+            let g = gene(i);
+            [g.r, g.g, g.b]
+        })
+        .collect();
+    let rule = |state: usize, count: usize| {
+        let entry = state * COUNTS + count;
+        (rules[entry / 4] >> (2 * (entry % 4))) as usize % STATES
+    };
+
+    let mut cells = vec![0; width * height];
+    let seed = gene(SEED_GENE);
+    let seed = u32::from_le_bytes([seed.r, seed.g, seed.b, 0]);
+    for k in 0..9 {
+        let x = (width / 2 + k % 3).checked_sub(1).filter(|&x| x < width);
+        let y = (height / 2 + k / 3).checked_sub(1).filter(|&y| y < height);
+        if let (Some(x), Some(y)) = (x, y) {
+            cells[y * width + x] = (seed >> (2 * k)) as usize % STATES;
+        }
+    }
+
+    let mut next = vec![0; width * height];
+    for _ in 0..steps {
+        for y in 0..height {
+            for x in 0..width {
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if (nx, ny) != (x, y) && cells[ny * width + nx] != 0 {
+                            count += 1;
+                        }
+                    }
+                }
+                next[y * width + x] = rule(cells[y * width + x], count);
+            }
+        }
+        std::mem::swap(&mut cells, &mut next);
+    }
+    Genome::new(cells.into_iter().map(gene).collect(), width, height)
+}
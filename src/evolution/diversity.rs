@@ -0,0 +1,177 @@
+use crate::evolution::gene::{Compare, Genome};
+
+/// Similarity of every pair of genomes, the diagonal is 1
+pub fn similarity_matrix<T: Compare + Clone>(genomes: &[&Genome<T>]) -> Vec<Vec<f32>> {
+    let n = genomes.len();
+    let mut matrix = vec![vec![1.0; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let similarity = genomes[i].compare(genomes[j]);
+            matrix[i][j] = similarity;
+            matrix[j][i] = similarity;
+        }
+    }
+    matrix
+}
+
+/// Mean similarity of all distinct pairs, 1 for less than two genomes
+pub fn mean_pairwise_similarity(matrix: &[Vec<f32>]) -> f32 {
+    let n = matrix.len();
+    if n < 2 {
+        return 1.0;
+    }
+    let mut total = 0.0;
+    for i in 0..n {
+        for j in (i + 1)..n {
+            total += matrix[i][j];
+        }
+    }
+    total / (n * (n - 1) / 2) as f32
+}
+
+/// Shannon entropy of every gene position, normalized to [0, 1]
+/// bucket sorts a gene into one of buckets classes, e.g. a coarse color
+pub fn gene_entropy<T>(genomes: &[&Genome<T>], buckets: usize, bucket: fn(&T) -> usize) -> Vec<f32> {
+    let size = genomes.iter().map(|g| g.len()).min().unwrap_or(0);
+    // the entropy can not exceed the log of the amount of distinct outcomes
+    let max = (genomes.len().min(buckets) as f32).ln();
+    let mut counts = vec![0u32; buckets];
+    (0..size)
+        .map(|at| {
+            counts.iter_mut().for_each(|c| *c = 0);
+            for genome in genomes {
+                counts[bucket(&genome[at]).min(buckets - 1)] += 1;
+            }
+            let n = genomes.len() as f32;
+            let entropy: f32 = counts
+                .iter()
+                .filter(|&&c| c > 0)
+                .map(|&c| {
+                    let p = c as f32 / n;
+                    -p * p.ln()
+                })
+                .sum();
+            if max > 0.0 {
+                entropy / max
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// Amount of groups in which every member is at least threshold similar to another member
+pub fn clusters(matrix: &[Vec<f32>], threshold: f32) -> usize {
+    let n = matrix.len();
+    let mut visited = vec![false; n];
+    let mut amount = 0;
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        amount += 1;
+        visited[start] = true;
+        let mut open = vec![start];
+        while let Some(i) = open.pop() {
+            for j in 0..n {
+                if !visited[j] && matrix[i][j] >= threshold {
+                    visited[j] = true;
+                    open.push(j);
+                }
+            }
+        }
+    }
+    amount
+}
+
+/// Ways of keeping a population from converging onto a single solution too early
+/// Distances are 1 - Compare similarity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preservation {
+    None,
+    /// fitness is divided by the amount of neighbours closer than radius
+    Sharing { radius: f32 },
+    /// a child replaces the most similar of factor random creatures, if it is better
+    Crowding { factor: usize },
+    /// only the best capacity creatures of every niche of size radius keep their fitness
+    Niching { radius: f32, capacity: usize },
+    /// children are bred inside species of genomes closer than threshold, see evolution::species
+    /// species which did not improve for stagnation generations die out
+    Speciation { threshold: f32, stagnation: usize },
+}
+
+impl Preservation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preservation::None => "none",
+            Preservation::Sharing { .. } => "fitness sharing",
+            Preservation::Crowding { .. } => "crowding",
+            Preservation::Niching { .. } => "niching",
+            Preservation::Speciation { .. } => "speciation",
+        }
+    }
+
+    /// The name --preservation picks it by
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Preservation::Sharing { .. } => "sharing",
+            _ => self.name(),
+        }
+    }
+
+    /// One of every kind with sensible parameters
+    pub fn all() -> [Preservation; 5] {
+        [
+            Preservation::None,
+            Preservation::Sharing { radius: 0.1 },
+            Preservation::Crowding { factor: 4 },
+            Preservation::Niching { radius: 0.05, capacity: 2 },
+            Preservation::Speciation { threshold: 0.1, stagnation: 15 },
+        ]
+    }
+
+    /// Turns raw costs into costs which respect the diversity of the population, lower is better
+    pub fn adjust(&self, costs: &[f32], matrix: &[Vec<f32>]) -> Vec<f32> {
+        match *self {
+            // speciation adjusts costs by itself, since it needs to know the species
+            Preservation::None | Preservation::Crowding { .. } | Preservation::Speciation { .. } => costs.to_vec(),
+            Preservation::Sharing { radius } => {
+                // costs can be negative, so sharing happens on a positive fitness
+                let worst = costs.iter().cloned().fold(f32::MIN, f32::max);
+                costs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &cost)| {
+                        let niche: f32 = matrix[i]
+                            .iter()
+                            .map(|&similarity| (1.0 - (1.0 - similarity) / radius).max(0.0))
+                            .sum();
+                        let fitness = worst - cost + f32::EPSILON;
+                        -fitness / niche.max(1.0)
+                    })
+                    .collect()
+            }
+            Preservation::Niching { radius, capacity } => {
+                let best = costs.iter().cloned().fold(f32::MAX, f32::min);
+                let worst = costs.iter().cloned().fold(f32::MIN, f32::max);
+                let mut order: Vec<usize> = (0..costs.len()).collect();
+                order.sort_unstable_by(|&a, &b| costs[a].total_cmp(&costs[b]));
+                let mut winners: Vec<usize> = Vec::new();
+                let mut adjusted = costs.to_vec();
+                for &i in order.iter() {
+                    let crowded = winners
+                        .iter()
+                        .filter(|&&w| 1.0 - matrix[i][w] < radius)
+                        .count();
+                    if crowded < capacity {
+                        winners.push(i);
+                    } else {
+                        // cleared creatures rank behind everybody else, but keep their order
+                        adjusted[i] = costs[i] + (worst - best) + 1.0;
+                    }
+                }
+                adjusted
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt;
+
+use crate::evolution::square::Symmetry;
+
+/// Why a genome, population or controller could not be created or evolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvolutionError {
+    EmptyGenome { width: usize, height: usize },
+    /// the genes do not fill the fundamental domain of the symmetry
+    GeneCount { width: usize, height: usize, symmetry: Symmetry, expected: usize, found: usize },
+    EmptyPopulation,
+    /// a setting of a builder which is out of range
+    InvalidSetting { setting: &'static str, expected: &'static str },
+    /// a crossover index which the index type of the population cannot hold
+    InvalidIndex { index: usize, max: usize },
+    /// crossover needs one index per gene
+    IndexCount { genes: usize, indices: usize },
+    /// a placement which found room for fewer blobs than the population has
+    Placement { placement: &'static str, placed: usize, blobs: usize },
+}
+
+impl fmt::Display for EvolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvolutionError::EmptyGenome { width, height } => {
+                write!(f, "a genome needs at least one gene, not {}x{}", width, height)
+            }
+            EvolutionError::GeneCount { width, height, symmetry, expected, found } => write!(
+                f,
+                "a {}x{} {} genome needs {} genes, not {}",
+                width,
+                height,
+                symmetry.name(),
+                expected,
+                found
+            ),
+            EvolutionError::EmptyPopulation => write!(f, "a population needs at least one blob"),
+            EvolutionError::InvalidSetting { setting, expected } => write!(f, "the {} needs to be {}", setting, expected),
+            EvolutionError::InvalidIndex { index, max } => {
+                write!(f, "crossover index {} does not fit, the index type holds up to {}", index, max)
+            }
+            EvolutionError::IndexCount { genes, indices } => {
+                write!(f, "a genome with {} genes cannot be combined along {} indices", genes, indices)
+            }
+            EvolutionError::Placement { placement, placed, blobs } => {
+                write!(f, "the {} placement found room for {} of {} blobs", placement, placed, blobs)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvolutionError {}
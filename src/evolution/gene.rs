@@ -1,21 +1,156 @@
-use nannou::math::num_traits::{PrimInt, ToPrimitive};
+use std::borrow::Cow;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use nannou::math::num_traits::PrimInt;
 use rand::Rng;
 
-use crate::util::{rnd_exp, Create};
+use crate::{
+    evolution::{
+        diversity::{similarity_matrix, Preservation},
+        error::EvolutionError,
+        lineage::Lineage,
+        species::Speciation,
+        square::{domain_positions, Region, Symmetry, Transform},
+    },
+    util::{rnd_exp, rng, Create},
+};
 
 /// A CostFunction determines the cost of an information T
-pub type CostFunction<T> = fn(&T) -> f32;
+/// It is reference counted so it can carry its own data, e.g. a reference image
+pub type CostFunction<T> = Rc<dyn Fn(&T) -> f32>;
+
+/// A Genome is a width * height grid of heritable pieces of information T
+/// The genes are stored row by row, starting with the bottom row
+/// A symmetric genome only stores its fundamental domain, see square::Symmetry
+/// Indexing it reaches the stored genes, Square::get reaches the whole grid
+#[derive(Debug, Clone)]
+pub struct Genome<T> {
+    genes: Vec<T>,
+    width: usize,
+    height: usize,
+    symmetry: Symmetry,
+}
+
+impl<T> Genome<T> {
+    pub fn new(genes: Vec<T>, width: usize, height: usize) -> Self {
+        Genome::symmetric(genes, width, height, Symmetry::None)
+    }
+
+    /// genes holds the fundamental domain only
+    /// Panics if the genes do not fit, use try_symmetric for genes from outside
+    pub fn symmetric(genes: Vec<T>, width: usize, height: usize, symmetry: Symmetry) -> Self {
+        match Genome::try_symmetric(genes, width, height, symmetry) {
+            Ok(genome) => genome,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_symmetric(genes: Vec<T>, width: usize, height: usize, symmetry: Symmetry) -> Result<Self, EvolutionError> {
+        let expected = symmetry.domain_size(width, height);
+        if genes.len() != expected {
+            return Err(EvolutionError::GeneCount { width, height, symmetry, expected, found: genes.len() });
+        }
+        Ok(Genome { genes, width, height, symmetry })
+    }
+
+    pub fn filled(value: T, width: usize, height: usize) -> Self
+    where
+        T: Clone,
+    {
+        Genome::new(vec![value; width * height], width, height)
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn symmetry(&self) -> Symmetry {
+        self.symmetry
+    }
+
+    pub fn into_genes(self) -> Vec<T> {
+        self.genes
+    }
+
+    /// The index of the stored gene which is shown at (x, y)
+    pub fn gene_index(&self, x: usize, y: usize) -> usize {
+        self.symmetry.fold(x, y, self.width, self.height)
+    }
+
+    /// A position of the grid which shows the stored gene at
+    pub fn position(&self, at: usize) -> (usize, usize) {
+        match self.symmetry {
+            Symmetry::None => (at % self.width, at / self.width),
+            symmetry => domain_positions(symmetry, self.width, self.height)[at],
+        }
+    }
+
+    /// The whole grid without symmetry, borrowed if there is none
+    pub fn expanded(&self) -> Cow<'_, Genome<T>>
+    where
+        T: Clone,
+    {
+        if self.symmetry == Symmetry::None {
+            return Cow::Borrowed(self);
+        }
+        let genes = (0..self.width * self.height)
+            .map(|i| self.genes[self.gene_index(i % self.width, i / self.width)].clone())
+            .collect();
+        Cow::Owned(Genome::new(genes, self.width, self.height))
+    }
+
+    /// Keeps the fundamental domain of the symmetry, the rest of the grid is overwritten by its mirror images
+    pub fn folded(&self, symmetry: Symmetry) -> Genome<T>
+    where
+        T: Clone,
+    {
+        let expanded = self.expanded();
+        let genes = domain_positions(symmetry, self.width, self.height)
+            .iter()
+            .map(|&(x, y)| expanded.genes[y * self.width + x].clone())
+            .collect();
+        Genome::symmetric(genes, self.width, self.height, symmetry)
+    }
+
+    /// The gene of other which lies on the stored gene at index at, if both genomes are centered on each other
+    pub fn aligned<'a>(&self, other: &'a Genome<T>, at: usize) -> Option<&'a T> {
+        let (x, y) = self.position(at);
+        // rounds towards zero, so aligning a with b is the reverse of aligning b with a
+        let x = x as isize + (other.width as isize - self.width as isize) / 2;
+        let y = y as isize + (other.height as isize - self.height as isize) / 2;
+        if x < 0 || y < 0 || x >= other.width as isize || y >= other.height as isize {
+            return None;
+        }
+        other.genes.get(other.gene_index(x as usize, y as usize))
+    }
+}
+
+impl<T> Deref for Genome<T> {
+    type Target = [T];
 
-/// A Genome is a set of heritable pieces of information T
-pub type Genome<T> = Vec<T>;
+    fn deref(&self) -> &[T] {
+        &self.genes
+    }
+}
+
+impl<T> DerefMut for Genome<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.genes
+    }
+}
 
 /// The trait which a Genome needs to fulfill
 pub trait Genetic<T> {
     /// Randomly replaces a single information inside a Genome
-    fn mutate_at(&mut self, at: usize);
+    fn mutate_at(&mut self, at: usize) -> Result<(), EvolutionError>;
+
+    /// Adds or removes rows and columns around the center, returns the indices of the new genes
+    fn resize(&mut self, width: usize, height: usize) -> Result<Vec<usize>, EvolutionError>;
 
     /// Combines parts of yourself with other Genomes according to the indices vector
-    fn combine<S: PrimInt>(&mut self, fathers: &Vec<Self>, indices: &Vec<S>)
+    /// Fathers of a different size are aligned on the center, genes they do not have are kept
+    fn combine<S: PrimInt>(&mut self, fathers: &Vec<Self>, indices: &Vec<S>) -> Result<(), EvolutionError>
     where
         Self: Sized;
 }
@@ -24,6 +159,31 @@ pub trait Genetic<T> {
 pub trait Creature<T: Create + Clone + Copy> {
     /// A Creature can provide access to its Genome through this interface method
     fn extract_genome(&mut self) -> &mut Genome<T>;
+
+    /// A Creature knows who it is and where it came from
+    fn extract_lineage(&mut self) -> &mut Lineage;
+}
+
+/// Where a single gene of a child came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    Mother,
+    /// the index into the fathers of the Birth
+    Father(usize),
+    Mutated,
+}
+
+/// Describes how a single Creature of a population was replaced by a child
+/// Indices point into the population at the time the child was born
+#[derive(Debug, Clone)]
+pub struct Birth {
+    pub mother: usize,
+    pub fathers: Vec<usize>,
+    /// the creature which was replaced, usually the mother herself
+    pub slot: usize,
+    pub mutations: usize,
+    /// one entry per gene
+    pub provenance: Vec<Provenance>,
 }
 
 /// rates similarity in percent
@@ -35,56 +195,127 @@ pub trait Compare {
 impl<T: Create + Clone + Copy> Genetic<T> for Genome<T>
 {
     /// Requires the information to implement Create
-    fn mutate_at(&mut self, at: usize) {
-        self[at] = T::create();
+    fn mutate_at(&mut self, at: usize) -> Result<(), EvolutionError> {
+        self[at] = T::create()?;
+        Ok(())
+    }
+
+    /// New genes are created randomly, the symmetry is kept
+    fn resize(&mut self, width: usize, height: usize) -> Result<Vec<usize>, EvolutionError> {
+        if width == 0 || height == 0 {
+            return Err(EvolutionError::EmptyGenome { width, height });
+        }
+        let symmetry = self.symmetry;
+        let size = symmetry.domain_size(width, height);
+        let mut genes = Vec::with_capacity(size);
+        let mut created = Vec::new();
+        let resized = Genome { genes: Vec::new(), width, height, symmetry };
+        for at in 0..size {
+            match resized.aligned(self, at) {
+                Some(&gene) => genes.push(gene),
+                None => {
+                    genes.push(T::create()?);
+                    created.push(at);
+                }
+            }
+        }
+        *self = Genome::symmetric(genes, width, height, symmetry);
+        Ok(created)
     }
 
     /// Assumes that each number in indices is pointing to a father
     /// if n >= fathers.len, the mother information is used
-    fn combine<S: PrimInt>(&mut self, fathers: &Vec<Self>, indices: &Vec<S>) {
-        assert!(self.len() == indices.len());
+    /// Fails if there is not exactly one index per gene
+    fn combine<S: PrimInt>(&mut self, fathers: &Vec<Self>, indices: &Vec<S>) -> Result<(), EvolutionError> {
+        if self.len() != indices.len() {
+            return Err(EvolutionError::IndexCount { genes: self.len(), indices: indices.len() });
+        }
 
         for (at, from) in indices.iter().enumerate() {
-            let from = from.to_usize().unwrap();
-            if from >= fathers.len() {
-                continue;
+            // negative indices point to no father either
+            let from = match from.to_usize() {
+                Some(from) if from < fathers.len() => from,
+                _ => continue,
+            };
+            if let Some(&gene) = self.aligned(&fathers[from], at) {
+                self[at] = gene;
             }
-            self[at] = fathers[from][at];
         }
+        Ok(())
     }
 }
 
-impl<T> Create for Genome<T>
-where
-    T: Create + Clone + Copy,
+impl<T> Compare for Genome<T>
+where T: Compare + Clone
 {
-    type Params = usize;
-
-    fn create() -> Self {
-        todo!()
+    /// Genomes of different sizes are aligned on the center
+    /// genes without a counterpart count as completely different
+    /// Symmetric genomes are compared on their whole grid
+    fn compare(&self, to: &Self) -> f32 {
+        if self.symmetry != Symmetry::None || to.symmetry != Symmetry::None {
+            return self.expanded().compare(&to.expanded());
+        }
+        let mut similarity = 0.0;
+        let norm = self.len().max(to.len()) as f32;
+        for (at, a) in self.iter().enumerate() {
+            if let Some(b) = self.aligned(to, at) {
+                similarity += a.compare(b);
+            }
+        }
+        similarity / norm
     }
+}
+
+/// How the genes of the fathers are picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossover {
+    /// every gene comes from a random parent
+    Uniform,
+    /// every father passes on a random Region, e.g. a rectangle or a ring
+    Regions,
+}
+
+impl Crossover {
+    pub const ALL: [Crossover; 2] = [Crossover::Uniform, Crossover::Regions];
 
-    fn create_like(params: Option<Self::Params>) -> Self {
-        let size = params.unwrap();
-        let mut genome = Vec::with_capacity(size);
-        for _ in 0..size {
-            genome.push(T::create());
+    pub fn name(&self) -> &'static str {
+        match self {
+            Crossover::Uniform => "uniform",
+            Crossover::Regions => "regions",
         }
-        genome
     }
 }
 
-impl<T> Compare for Genome<T>
-where T: Compare
-{
-    fn compare(&self, to: &Self) -> f32 {
-        assert!(self.len() == to.len(), "Can not compare genomes of different sizes");
-        let mut similarity = 0.0;
-        let norm = self.len() as f32;
-        for (a,b) in self.iter().zip(to.iter()) {
-            similarity += a.compare(b);
+/// The operator settings of a genetic algorithm
+#[derive(Debug, Clone, Copy)]
+pub struct EvolveSettings {
+    /// amount of fathers per child
+    pub fathers: usize,
+    pub crossover: Crossover,
+    /// expected amount of mutations per child
+    pub mutations: usize,
+    /// chance of a child to grow or shrink by a row or column, 0 keeps every genome at its size
+    pub resize: f32,
+    /// bounds of the width and height of a resized genome
+    pub min_side: usize,
+    pub max_side: usize,
+    /// chance of a child to be rotated, mirrored or shifted as a whole, see square::Transform
+    pub transform: f32,
+    pub preservation: Preservation,
+}
+
+impl Default for EvolveSettings {
+    fn default() -> Self {
+        EvolveSettings {
+            fathers: 1,
+            crossover: Crossover::Uniform,
+            mutations: 2,
+            resize: 0.0,
+            min_side: 4,
+            max_side: 32,
+            transform: 0.0,
+            preservation: Preservation::None,
         }
-        similarity / norm
     }
 }
 
@@ -94,86 +325,273 @@ where T: Compare
 /// S: A number defining the maximum size of the population
 pub trait Evolve<T, R, S>
 where
-    T: Create + Clone + Copy,
+    T: Create + Clone + Copy + Compare,
     R: Creature<T>,
     S: PrimInt,
 {
-    /// Puts the genome with lowest cost first
-    fn weight(genome_pool: &mut Vec<&mut Genome<T>>, rate_fitness: CostFunction<Genome<T>>) {
-        genome_pool.sort_unstable_by_key(|p| rate_fitness(p) as i32);
-    }
-
-    /// Randomly chooses a pool of fathers from the population
-    /// Todo: remove cloning for speed up
-    fn get_fathers(
-        genome_pool: &Vec<&mut Genome<T>>,
-        rho: usize,
-        diversity: usize,
-    ) -> Vec<Genome<T>> {
+    /// Rates every creature of the population once
+    fn rate(population: &mut Vec<R>, rate_fitness: &CostFunction<Genome<T>>) -> Vec<f32> {
+        population
+            .iter_mut()
+            .map(|creature| rate_fitness(creature.extract_genome()))
+            .collect()
+    }
+
+    /// Ranks the costs, the index of the creature with lowest cost comes first
+    fn weight(costs: &[f32]) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..costs.len()).collect();
+        ranking.sort_unstable_by(|&a, &b| costs[a].total_cmp(&costs[b]));
+        ranking
+    }
+
+    /// Randomly chooses a pool of fathers from the ranking
+    fn get_fathers(ranking: &Vec<usize>, rho: usize, diversity: usize) -> Vec<usize> {
         let mut fathers = Vec::with_capacity(rho);
         while fathers.len() < rho {
             let index: usize = rnd_exp(diversity);
-            fathers.push(genome_pool[index % genome_pool.len()].clone());
+            fathers.push(ranking[index % ranking.len()]);
         }
         fathers
     }
 
     /// Performs a mapping for (every t in Genome<T>) to its (parent in {mother; fathers})
-    fn get_indices(genome_size: usize, fathers: &Vec<Genome<T>>) -> Vec<S> {
-        assert!(fathers.len() < S::max_value().to_usize().unwrap_or(usize::max_value()));
-
-        // mapper function which maps a genome index to a father
-        let map = |_: usize| {
-            let result = S::from(rnd_exp((fathers.len() / 2).clamp(1, fathers.len())) % (fathers.len() + 1));
-            match result {
-                Some(x) => x,
-                None => panic!("Failed to convert usize into this PrimNum type"),
-            }
-        };
-        let size = genome_size.to_usize();
-        let size = match size {
-            Some(x) => x,
-            None => panic!("Failed to convert genome_size into usize"),
-        };
+    fn get_indices(genome_size: usize, fathers: &Vec<Genome<T>>) -> Result<Vec<S>, EvolutionError> {
+        let to_s = Self::index_converter(fathers.len())?;
         // figure out the intervals at which genetic information will be copied
-        let mut indices = Vec::with_capacity(size);
-        for i in 0..size {
-            indices.push(map(i));
+        (0..genome_size)
+            .map(|_| to_s(rnd_exp((fathers.len() / 2).clamp(1, fathers.len().max(1))) % (fathers.len() + 1)))
+            .collect()
+    }
+
+    /// Like get_indices, but every father passes on a random region of the grid of the child
+    /// Later fathers paint over earlier ones, the mother keeps everything else
+    fn get_region_indices(child: &Genome<T>, fathers: &Vec<Genome<T>>) -> Result<Vec<S>, EvolutionError> {
+        let to_s = Self::index_converter(fathers.len())?;
+        let (width, height) = child.dimensions();
+        let mut indices = vec![to_s(fathers.len())?; child.len()];
+        for k in 0..fathers.len() {
+            // random regions are hardly ever asked for twice, so they skip the cache
+            let region = Region::random(width, height);
+            for (at, index) in indices.iter_mut().enumerate() {
+                let (x, y) = child.position(at);
+                if region.contains(x, y, width, height) {
+                    *index = to_s(k)?;
+                }
+            }
         }
-        indices
+        Ok(indices)
     }
 
-    /// Adds <~expected> Mutations to a Genome
-    fn mutate(t: &mut Genome<T>, expected: usize) {
-        let mut rng = rand::thread_rng();
+    /// Converts indices up to fathers, which stands for the mother, into the index type S
+    fn index_converter(fathers: usize) -> Result<impl Fn(usize) -> Result<S, EvolutionError>, EvolutionError> {
+        let max = S::max_value().to_usize().unwrap_or(usize::MAX);
+        let to_s = move |index: usize| S::from(index).ok_or(EvolutionError::InvalidIndex { index, max });
+        to_s(fathers)?;
+        Ok(to_s)
+    }
+
+    /// Adds <~expected> Mutations to a Genome and returns where they happened
+    fn mutate(t: &mut Genome<T>, expected: usize) -> Result<Vec<usize>, EvolutionError> {
+        if t.is_empty() {
+            let (width, height) = t.dimensions();
+            return Err(EvolutionError::EmptyGenome { width, height });
+        }
+        let mut rng = rng();
         let mutation_amount = rnd_exp(expected);
+        let mut positions = Vec::with_capacity(mutation_amount);
         for _ in 0..mutation_amount {
             let at = rng.gen_range(0..t.len());
-            t.mutate_at(at);
+            t.mutate_at(at)?;
+            positions.push(at);
+        }
+        Ok(positions)
+    }
+
+    /// Picks the size of a child, which usually is the size of its mother
+    /// Only a resized side is kept within min_side and max_side, a side outside of them may only move toward them
+    fn child_dimensions(mother: (usize, usize), settings: &EvolveSettings) -> (usize, usize) {
+        let mut rng = rng();
+        let (mut width, mut height) = mother;
+        if rng.gen::<f32>() < settings.resize {
+            let (min, max) = (settings.min_side.max(1), settings.max_side.max(settings.min_side.max(1)));
+            let side = if rng.gen() { &mut width } else { &mut height };
+            let resized = if rng.gen() { *side + 1 } else { side.saturating_sub(1) };
+            *side = resized.clamp(min.min(*side), max.max(*side));
+        }
+        (width, height)
+    }
+
+    /// Combines the mother with the fathers and mutates the result
+    /// Returns the child, where its genes came from and the amount of mutations
+    fn breed(
+        population: &mut Vec<R>,
+        mother: usize,
+        fathers: &Vec<usize>,
+        settings: &EvolveSettings,
+    ) -> Result<(Genome<T>, Vec<Provenance>, usize), EvolutionError> {
+        // Todo: remove cloning for speed up
+        let father_genomes: Vec<Genome<T>> = fathers
+            .iter()
+            .map(|&i| population[i].extract_genome().clone())
+            .collect();
+        let mut child = population[mother].extract_genome().clone();
+        let (width, height) = Self::child_dimensions(child.dimensions(), settings);
+        let grown = if (width, height) != child.dimensions() {
+            child.resize(width, height)?
+        } else {
+            Vec::new()
+        };
+        let genome_size = child.len();
+        let indices = match settings.crossover {
+            Crossover::Uniform => Self::get_indices(genome_size, &father_genomes)?,
+            Crossover::Regions => Self::get_region_indices(&child, &father_genomes)?,
+        };
+        child.combine(&father_genomes, &indices)?;
+        let expected = settings.mutations.clamp(1, genome_size.max(2) - 1);
+        let mutations = Self::mutate(&mut child, expected)?;
+
+        let mut provenance: Vec<Provenance> = indices
+            .iter()
+            .enumerate()
+            .map(|(at, from)| match from.to_usize() {
+                Some(k) if k < fathers.len() && child.aligned(&father_genomes[k], at).is_some() => Provenance::Father(k),
+                _ => Provenance::Mother,
+            })
+            .collect();
+        // grown genes which no father covers are as random as mutations
+        let created: Vec<usize> = grown.into_iter().filter(|&at| provenance[at] == Provenance::Mother).collect();
+        for &at in created.iter().chain(mutations.iter()) {
+            provenance[at] = Provenance::Mutated;
+        }
+        let mutations = created.len() + mutations.len();
+
+        if rng().gen::<f32>() < settings.transform {
+            // the provenance moves along with the genes
+            // other transforms would fold away half of a symmetric genome
+            let transform = Transform::random_for(child.symmetry());
+            let provenance = Genome::symmetric(provenance, width, height, child.symmetry())
+                .transformed(transform)
+                .into_genes();
+            return Ok((child.transformed(transform), provenance, mutations));
         }
+        Ok((child, provenance, mutations))
     }
 
     /// One iteration of a genetic algorithm
     /// It manipulates the genomes of a population to form a new generation
-    fn evolve(population: &mut Vec<R>, rate_fitness: CostFunction<Genome<T>>) {
-        let mut genome_pool: Vec<&mut Genome<T>> = Vec::with_capacity(population.len());
-        for creature in population.iter_mut() {
-            genome_pool.push(creature.extract_genome());
-        }
-        let size = genome_pool.len();
-        Self::weight(&mut genome_pool, rate_fitness);
-
-        let mut index = size;
-
-        while index > 0 {
-            let fathers = Self::get_fathers(&genome_pool, 1, size / 2);
-            let mother = &mut genome_pool[index - 1];
-            let genome_size = mother.len();
-            let indices = Self::get_indices(genome_size, &fathers);
-            mother.combine(&fathers, &indices);
-            let expected = (2).clamp(1, genome_size - 1);
-            Self::mutate(mother, expected);
-            index -= 1;
+    /// Returns every birth in the order they happened, or why the population could not evolve
+    fn evolve(
+        population: &mut Vec<R>,
+        rate_fitness: &CostFunction<Genome<T>>,
+        settings: &EvolveSettings,
+        speciation: &mut Speciation<T>,
+    ) -> Result<Vec<Birth>, EvolutionError> {
+        let costs = Self::rate(population, rate_fitness);
+        Self::evolve_rated(population, costs, rate_fitness, settings, speciation)
+    }
+
+    /// Like evolve, but the costs of the population are already known, e.g. because a person rated it
+    /// rate_fitness is only asked about children
+    fn evolve_rated(
+        population: &mut Vec<R>,
+        mut costs: Vec<f32>,
+        rate_fitness: &CostFunction<Genome<T>>,
+        settings: &EvolveSettings,
+        speciation: &mut Speciation<T>,
+    ) -> Result<Vec<Birth>, EvolutionError> {
+        let size = population.len();
+        if size == 0 {
+            return Err(EvolutionError::EmptyPopulation);
+        }
+        if let Preservation::Speciation { threshold, stagnation } = settings.preservation {
+            return Self::evolve_species(population, &costs, settings, speciation, threshold, stagnation);
+        }
+        speciation.clear();
+        let ranking = match settings.preservation {
+            Preservation::Sharing { .. } | Preservation::Niching { .. } => {
+                let genomes: Vec<&Genome<T>> = population.iter_mut().map(|c| &*c.extract_genome()).collect();
+                let matrix = similarity_matrix(&genomes);
+                Self::weight(&settings.preservation.adjust(&costs, &matrix))
+            }
+            _ => Self::weight(&costs),
+        };
+        let mut births = Vec::with_capacity(size);
+        let mut rng = rng();
+
+        // the worst creatures are replaced first
+        for &mother in ranking.iter().rev() {
+            let fathers = Self::get_fathers(&ranking, settings.fathers.max(1), (size / 2).max(1));
+            let (child, provenance, mutations) = Self::breed(population, mother, &fathers, settings)?;
+
+            let slot = match settings.preservation {
+                Preservation::Crowding { factor } => {
+                    // the child competes with the most similar of a few random creatures
+                    let target = (0..factor.max(1))
+                        .map(|_| rng.gen_range(0..size))
+                        .max_by(|&a, &b| {
+                            let a = child.compare(population[a].extract_genome());
+                            let b = child.compare(population[b].extract_genome());
+                            a.total_cmp(&b)
+                        })
+                        .unwrap_or(mother);
+                    let cost = rate_fitness(&child);
+                    if cost > costs[target] {
+                        continue;
+                    }
+                    costs[target] = cost;
+                    target
+                }
+                _ => mother,
+            };
+            *population[slot].extract_genome() = child;
+            births.push(Birth {
+                mother,
+                fathers,
+                slot,
+                mutations,
+                provenance,
+            });
+        }
+        Ok(births)
+    }
+
+    /// Every species breeds its quota of children from its own members
+    /// The children replace the worst creatures of the whole population and inherit the species of their parents
+    fn evolve_species(
+        population: &mut Vec<R>,
+        costs: &[f32],
+        settings: &EvolveSettings,
+        speciation: &mut Speciation<T>,
+        threshold: f32,
+        stagnation: usize,
+    ) -> Result<Vec<Birth>, EvolutionError> {
+        let size = population.len();
+        let genomes: Vec<&Genome<T>> = population.iter_mut().map(|c| &*c.extract_genome()).collect();
+        speciation.speciate(&genomes, costs, threshold);
+        let adjusted = speciation.adjust(costs);
+        let quotas = speciation.quotas(&adjusted, size, stagnation);
+
+        let mut slots = Self::weight(&adjusted).into_iter().rev();
+        let mut births = Vec::with_capacity(size);
+        for (species, &quota) in quotas.iter().enumerate() {
+            let mut ranking = speciation.species[species].members.clone();
+            ranking.sort_unstable_by(|&a, &b| adjusted[a].total_cmp(&adjusted[b]));
+            let diversity = (ranking.len() / 2).max(1);
+            for slot in slots.by_ref().take(quota) {
+                let mother = ranking[rnd_exp(diversity) % ranking.len()];
+                let fathers = Self::get_fathers(&ranking, settings.fathers.max(1), diversity);
+                let (child, provenance, mutations) = Self::breed(population, mother, &fathers, settings)?;
+                *population[slot].extract_genome() = child;
+                speciation.labels[slot] = species;
+                births.push(Birth {
+                    mother,
+                    fathers,
+                    slot,
+                    mutations,
+                    provenance,
+                });
+            }
         }
+        Ok(births)
     }
 }
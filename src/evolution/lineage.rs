@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::{
+    evolution::gene::{Birth, Creature},
+    util::Create,
+};
+
+/// The identity of an individual and the ids of its parents
+#[derive(Debug, Clone, Default)]
+pub struct Lineage {
+    pub id: u64,
+    /// the generation this individual was born in
+    pub born: usize,
+    pub mother: Option<u64>,
+    pub fathers: Vec<u64>,
+}
+
+/// Remembers the Lineage of every individual which still has living descendants
+pub struct Genealogy {
+    records: HashMap<u64, Lineage>,
+    next_id: u64,
+    /// individuals older than this many generations are forgotten, otherwise every maternal line grows forever
+    pub horizon: usize,
+}
+
+impl Genealogy {
+    pub fn new() -> Self {
+        Genealogy {
+            records: HashMap::new(),
+            next_id: 0,
+            horizon: 1024,
+        }
+    }
+
+    fn register(&mut self, born: usize, mother: Option<u64>, fathers: Vec<u64>) -> Lineage {
+        let lineage = Lineage {
+            id: self.next_id,
+            born,
+            mother,
+            fathers,
+        };
+        self.next_id += 1;
+        self.records.insert(lineage.id, lineage.clone());
+        lineage
+    }
+
+    /// Gives every creature of the population a fresh id without parents
+    pub fn found<T, R>(&mut self, population: &mut Vec<R>, generation: usize)
+    where
+        T: Create + Clone + Copy,
+        R: Creature<T>,
+    {
+        for creature in population.iter_mut() {
+            self.found_creature(creature, generation);
+        }
+    }
+
+    /// Gives a single creature a fresh id without parents, e.g. an immigrant
+    pub fn found_creature<T, R>(&mut self, creature: &mut R, generation: usize)
+    where
+        T: Create + Clone + Copy,
+        R: Creature<T>,
+    {
+        *creature.extract_lineage() = self.register(generation, None, Vec::new());
+    }
+
+    /// Replays the births of a generation to hand out ids
+    /// Births have to be in the order they happened, since fathers may be children of the same generation
+    pub fn record<T, R>(&mut self, population: &mut Vec<R>, births: &[Birth], generation: usize)
+    where
+        T: Create + Clone + Copy,
+        R: Creature<T>,
+    {
+        // the ids of the population before the genomes were replaced
+        let mut ids: Vec<u64> = population.iter_mut().map(|c| c.extract_lineage().id).collect();
+        for birth in births {
+            let fathers = birth.fathers.iter().map(|&i| ids[i]).collect();
+            let lineage = self.register(generation, Some(ids[birth.mother]), fathers);
+            ids[birth.slot] = lineage.id;
+            *population[birth.slot].extract_lineage() = lineage;
+        }
+    }
+
+    /// Learns the lineages of a restored population again, they may have been pruned in the meantime
+    pub fn remember(&mut self, lineages: &[Lineage]) {
+        for lineage in lineages {
+            self.records.entry(lineage.id).or_insert_with(|| lineage.clone());
+        }
+    }
+
+    fn parents(&self, id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.records
+            .get(&id)
+            .into_iter()
+            .flat_map(|l| l.mother.iter().chain(l.fathers.iter()).copied())
+    }
+
+    /// All known ancestors of an individual, including itself
+    pub fn ancestors(&self, id: u64) -> HashSet<u64> {
+        let mut visited = HashSet::new();
+        let mut open = vec![id];
+        while let Some(current) = open.pop() {
+            if visited.insert(current) {
+                open.extend(self.parents(current));
+            }
+        }
+        visited
+    }
+
+    /// The youngest individual every one of the ids descends from
+    pub fn most_recent_common_ancestor(&self, ids: &[u64]) -> Option<u64> {
+        let mut ids = ids.iter();
+        let mut common = self.ancestors(*ids.next()?);
+        for &id in ids {
+            let ancestors = self.ancestors(id);
+            common.retain(|a| ancestors.contains(a));
+        }
+        common
+            .into_iter()
+            .filter_map(|id| self.records.get(&id))
+            .max_by_key(|l| (l.born, l.id))
+            .map(|l| l.id)
+    }
+
+    /// Forgets every individual without living descendants or born behind the horizon
+    pub fn prune(&mut self, living: &[u64], generation: usize) {
+        let oldest = generation.saturating_sub(self.horizon);
+        self.records.retain(|_, l| l.born >= oldest);
+        let mut keep = HashSet::new();
+        for &id in living {
+            if !keep.contains(&id) {
+                keep.extend(self.ancestors(id));
+            }
+        }
+        self.records.retain(|id, _| keep.contains(id));
+    }
+
+    fn sorted(&self) -> Vec<&Lineage> {
+        let mut records: Vec<&Lineage> = self.records.values().collect();
+        records.sort_unstable_by_key(|l| l.id);
+        records
+    }
+
+    /// A GraphViz family tree, mothers are drawn solid and fathers dashed
+    /// The living individuals are highlighted
+    pub fn to_dot(&self, living: &[u64]) -> String {
+        let mut dot = String::from("digraph genealogy {\n    rankdir=TB;\n    node [shape=box];\n");
+        for lineage in self.sorted() {
+            let style = if living.contains(&lineage.id) { ", style=filled, fillcolor=lightyellow" } else { "" };
+            let _ = writeln!(dot, "    n{} [label=\"{}\\ngen {}\"{}];", lineage.id, lineage.id, lineage.born, style);
+            if let Some(mother) = lineage.mother.filter(|m| self.records.contains_key(m)) {
+                let _ = writeln!(dot, "    n{} -> n{};", mother, lineage.id);
+            }
+            for father in lineage.fathers.iter().filter(|f| self.records.contains_key(f)) {
+                let _ = writeln!(dot, "    n{} -> n{} [style=dashed];", father, lineage.id);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .sorted()
+            .iter()
+            .map(|l| {
+                let mother = l.mother.map_or("null".to_string(), |m| m.to_string());
+                let fathers: Vec<String> = l.fathers.iter().map(|f| f.to_string()).collect();
+                format!(
+                    "{{\"id\":{},\"born\":{},\"mother\":{},\"fathers\":[{}]}}",
+                    l.id,
+                    l.born,
+                    mother,
+                    fathers.join(",")
+                )
+            })
+            .collect();
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
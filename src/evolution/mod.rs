@@ -1,4 +1,12 @@
 pub mod blob;
+pub mod builder;
+pub mod development;
+pub mod diversity;
+pub mod error;
 pub mod gene;
+pub mod lineage;
+pub mod placement;
 pub mod population;
-pub mod square;
\ No newline at end of file
+pub mod species;
+pub mod square;
+pub mod target;
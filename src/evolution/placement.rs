@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::f32::consts::{PI, SQRT_2};
+
+use nannou::glam::Vec2;
+use rand::Rng;
+
+use crate::{
+    evolution::{
+        blob::RGB,
+        gene::Genome,
+        species::Speciation,
+    },
+    util::{rng, SharedRng},
+};
+
+/// Genomes which are less different than this end up in the same cluster, like the default speciation threshold
+const CLUSTER_THRESHOLD: f32 = 0.1;
+
+/// How far the points of a ring stray from their place on it, relative to the spacing
+const RING_JITTER: f32 = 0.1;
+
+/// Candidates Poisson disc sampling tries around a point before giving up on it
+const POISSON_TRIES: usize = 30;
+
+/// Where the blobs of a new population are placed, every layout is centered around the origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// concentric rings, the first one around the center
+    Rings,
+    /// random points which grow outward from the center, Bridson's algorithm
+    PoissonDisc,
+    HexGrid,
+    SquareGrid,
+    /// random points in a square just large enough to hold them
+    RandomRect,
+    /// genomes which look alike are placed next to each other, one patch per species
+    Clustered,
+}
+
+impl Placement {
+    pub const ALL: [Placement; 6] = [
+        Placement::Rings,
+        Placement::PoissonDisc,
+        Placement::HexGrid,
+        Placement::SquareGrid,
+        Placement::RandomRect,
+        Placement::Clustered,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::Rings => "rings",
+            Placement::PoissonDisc => "poisson",
+            Placement::HexGrid => "hex",
+            Placement::SquareGrid => "square",
+            Placement::RandomRect => "random",
+            Placement::Clustered => "clustered",
+        }
+    }
+
+    /// One position per genome, no two positions are closer than spacing
+    pub fn positions(&self, genomes: &[Genome<RGB>], spacing: f32) -> Vec<Vec2> {
+        let amount = genomes.len();
+        let mut rng = rng();
+        let positions = match self {
+            Placement::Rings => rings(&mut rng, amount, spacing),
+            Placement::PoissonDisc => poisson_disc(&mut rng, amount, spacing),
+            Placement::HexGrid => hex_grid(amount, spacing),
+            Placement::SquareGrid => square_grid(amount, spacing),
+            Placement::RandomRect => random_rect(&mut rng, amount, spacing),
+            Placement::Clustered => clustered(&mut rng, genomes, spacing),
+        };
+        spread(positions, spacing)
+    }
+}
+
+/// The distance between the centers of two blobs which cannot overlap, whatever side they are next to each other
+pub fn blob_spacing(width: usize, height: usize, gene_size: f32) -> f32 {
+    width.max(height) as f32 * gene_size * SQRT_2
+}
+
+/// Scales the positions around their center until no two are closer than spacing
+/// The layouts with random offsets use it as a guarantee, it leaves every other layout as it is
+fn spread(mut positions: Vec<Vec2>, spacing: f32) -> Vec<Vec2> {
+    let mut closest = f32::MAX;
+    for (i, a) in positions.iter().enumerate() {
+        for b in positions[i + 1..].iter() {
+            closest = closest.min(a.distance(*b));
+        }
+    }
+    if closest < spacing {
+        let center = positions.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / positions.len() as f32;
+        // a tiny margin, so rounding does not bring the closest pair below spacing again
+        let scale = spacing / closest.max(spacing * 1e-3) * 1.0001;
+        for position in positions.iter_mut() {
+            *position = center + (*position - center) * scale;
+        }
+    }
+    positions
+}
+
+/// Fills ring after ring around the center, each as full as its circumference allows
+fn rings(rng: &mut SharedRng, amount: usize, spacing: f32) -> Vec<Vec2> {
+    let mut points = Vec::with_capacity(amount);
+    let mut radius = 0.0;
+    while points.len() < amount {
+        // a circle of radius r holds 2 pi r / spacing points, the first ring is the center
+        let capacity = ((2.0 * PI * radius / spacing) as usize).max(1);
+        let step = 2.0 * PI / capacity as f32;
+        let start = rng.gen_range(0.0..2.0 * PI);
+        for i in 0..capacity.min(amount - points.len()) {
+            let angle = start + step * i as f32 + step * rng.gen_range(-RING_JITTER..=RING_JITTER);
+            let distance = radius + spacing * rng.gen_range(-RING_JITTER..=RING_JITTER);
+            points.push(Vec2::new(angle.cos(), angle.sin()) * distance);
+        }
+        radius += spacing;
+    }
+    points
+}
+
+/// Bridson's algorithm on an unbounded plane, stops as soon as there are enough points
+fn poisson_disc(rng: &mut SharedRng, amount: usize, spacing: f32) -> Vec<Vec2> {
+    // a cell this small holds at most one point
+    let cell = spacing / SQRT_2;
+    let key = |p: Vec2| ((p.x / cell).floor() as i32, (p.y / cell).floor() as i32);
+    let mut grid: HashMap<(i32, i32), usize> = HashMap::new();
+    let mut points = vec![Vec2::ZERO];
+    let mut active = vec![0];
+    grid.insert(key(Vec2::ZERO), 0);
+    while points.len() < amount {
+        let Some(&index) = active.get(rng.gen_range(0..active.len().max(1))) else {
+            // every point is surrounded, which cannot happen on an unbounded plane
+            break;
+        };
+        let origin = points[index];
+        let candidate = (0..POISSON_TRIES).map(|_| {
+            let angle = rng.gen_range(0.0..2.0 * PI);
+            origin + Vec2::new(angle.cos(), angle.sin()) * rng.gen_range(spacing..2.0 * spacing)
+        }).find(|&candidate| {
+            let (x, y) = key(candidate);
+            (x - 2..=x + 2).all(|i| {
+                (y - 2..=y + 2).all(|j| grid.get(&(i, j)).map_or(true, |&p| points[p].distance(candidate) >= spacing))
+            })
+        });
+        match candidate {
+            Some(candidate) => {
+                grid.insert(key(candidate), points.len());
+                active.push(points.len());
+                points.push(candidate);
+            }
+            None => {
+                active.retain(|&i| i != index);
+            }
+        }
+    }
+    points.truncate(amount);
+    points
+}
+
+/// Rows which are shifted by half a step, every point is spacing away from its six neighbours
+fn hex_grid(amount: usize, spacing: f32) -> Vec<Vec2> {
+    let row_height = spacing * 3f32.sqrt() / 2.0;
+    // about as many columns as rows take up a square
+    let columns = ((amount as f32 * row_height / spacing).sqrt().ceil() as usize).max(1);
+    let rows = (amount + columns - 1) / columns;
+    let center = Vec2::new((columns - 1) as f32 * spacing + spacing / 2.0, (rows - 1) as f32 * row_height) / 2.0;
+    (0..amount)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            let shift = if row % 2 == 1 { spacing / 2.0 } else { 0.0 };
+            Vec2::new(column as f32 * spacing + shift, row as f32 * row_height) - center
+        })
+        .collect()
+}
+
+fn square_grid(amount: usize, spacing: f32) -> Vec<Vec2> {
+    let columns = ((amount as f32).sqrt().ceil() as usize).max(1);
+    let rows = (amount + columns - 1) / columns;
+    let center = Vec2::new((columns - 1) as f32, (rows - 1) as f32) * spacing / 2.0;
+    (0..amount)
+        .map(|i| Vec2::new((i % columns) as f32, (i / columns) as f32) * spacing - center)
+        .collect()
+}
+
+/// Throws darts into a square which holds about twice as many points as needed
+/// Darts closer than spacing to an earlier one are thrown again, the square grows when too many miss
+fn random_rect(rng: &mut SharedRng, amount: usize, spacing: f32) -> Vec<Vec2> {
+    let mut half_side = (2.0 * amount as f32).sqrt() * spacing / 2.0;
+    let mut points: Vec<Vec2> = Vec::with_capacity(amount);
+    let mut misses = 0;
+    while points.len() < amount {
+        let candidate = Vec2::new(rng.gen_range(-half_side..=half_side), rng.gen_range(-half_side..=half_side));
+        if points.iter().all(|p| p.distance(candidate) >= spacing) {
+            points.push(candidate);
+            misses = 0;
+        } else {
+            misses += 1;
+            if misses > 100 {
+                half_side *= 1.1;
+                misses = 0;
+            }
+        }
+    }
+    points
+}
+
+/// Sorts the genomes into species and gives every species a disc of rings, the discs sit on a circle
+fn clustered(rng: &mut SharedRng, genomes: &[Genome<RGB>], spacing: f32) -> Vec<Vec2> {
+    let mut speciation = Speciation::new();
+    let references: Vec<&Genome<RGB>> = genomes.iter().collect();
+    speciation.speciate(&references, &vec![0.0; genomes.len()], CLUSTER_THRESHOLD);
+    let clusters: Vec<(Vec<Vec2>, f32)> = speciation
+        .species
+        .iter()
+        .map(|species| {
+            let points = rings(rng, species.members.len(), spacing);
+            let radius = points.iter().map(|p| p.length()).fold(0.0, f32::max);
+            (points, radius)
+        })
+        .collect();
+    // every cluster takes up a share of the circumference as large as its disc, plus one spacing between discs
+    let circumference: f32 = clusters.iter().map(|(_, radius)| 2.0 * radius + spacing).sum();
+    let circle = if clusters.len() > 1 { circumference / (2.0 * PI) } else { 0.0 };
+    let mut positions = vec![Vec2::ZERO; genomes.len()];
+    let mut arc = 0.0;
+    for (species, (points, radius)) in speciation.species.iter().zip(clusters.iter()) {
+        let angle = (arc + radius + spacing / 2.0) / circumference * 2.0 * PI;
+        arc += 2.0 * radius + spacing;
+        let center = Vec2::new(angle.cos(), angle.sin()) * circle;
+        for (&member, point) in species.members.iter().zip(points) {
+            positions[member] = center + *point;
+        }
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evolution::builder::{GenomeBuilder, Initializer},
+        util::seed,
+    };
+
+    #[test]
+    fn every_placement_keeps_the_spacing() {
+        let spacing = 12.5;
+        for placement in Placement::ALL {
+            for amount in [1, 2, 3, 7, 19, 64, 150] {
+                seed(amount as u64);
+                let genomes = Initializer::Random.genomes(&GenomeBuilder::new().dimensions(4, 4), amount).unwrap();
+                let positions = placement.positions(&genomes, spacing);
+                assert_eq!(positions.len(), amount, "{} placed {} blobs", placement.name(), amount);
+                for (i, a) in positions.iter().enumerate() {
+                    for b in positions[i + 1..].iter() {
+                        assert!(
+                            a.distance(*b) >= spacing,
+                            "{} placed two of {} blobs {} apart",
+                            placement.name(),
+                            amount,
+                            a.distance(*b)
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
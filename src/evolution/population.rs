@@ -1,14 +1,13 @@
 use std::ops::Neg;
+use std::rc::Rc;
 
 use nannou::glam::Vec2;
 
-use crate::{
-    evolution::{
-        blob::{Blob, RGB},
-        gene::{Evolve, Genome},
-        square::{Quadrant, Square},
-    },
-    util::Create,
+use crate::evolution::{
+    blob::{Blob, RGB},
+    gene::{CostFunction, Evolve, Genome},
+    square::{Quadrant, Square},
+    target::Distance,
 };
 
 use super::gene::Compare;
@@ -65,52 +64,35 @@ pub fn compare_to(genome: &Genome<RGB>, reference: &Genome<RGB>) -> f32 {
     - genome.compare(reference).exp()
 }
 
+/// Like compare_to, but the similarity is rated by a perceptual Distance
+pub fn compare_with(genome: &Genome<RGB>, reference: &Genome<RGB>, distance: Distance) -> f32 {
+    - distance.similarity(genome, reference).exp()
+}
+
+/// A CostFunction which rewards genomes looking like the reference
+pub fn match_reference(reference: Genome<RGB>, distance: Distance) -> CostFunction<Genome<RGB>> {
+    Rc::new(move |genome| compare_with(genome, &reference, distance))
+}
+
 /// Represents a collection of Blobs which are able to evolve
 pub type SimpleBlobPopulation = Vec<Blob>;
 
 impl Evolve<RGB, Blob, u16> for SimpleBlobPopulation {}
 
-impl Create for SimpleBlobPopulation {
-    type Params = (Vec<Vec2>, f32, u16, usize);
-
-    fn create_like(params: Option<Self::Params>) -> Self {
-        let (nannou_positions, nannou_size, population_size, genome_size) = params.unwrap();
-        let population_size = population_size as usize;
-        let mut blobs = Vec::with_capacity(population_size);
-        //create a random population
-        for i in 0..population_size {
-            //let genome = Genome::create_like(Some(genome_size));
-            let genome = create_runner(genome_size);
-            let nannou_position = nannou_positions[i];
-            let blob = Blob::new(genome, nannou_size, nannou_position);
-            blobs.push(blob);
-        }
-        blobs
-    }
-
-    fn create() -> Self {
-        todo!()
-    }
-}
-
-fn create_runner(size: usize) -> Genome<RGB> {
-    let root = (size as f32).sqrt() as usize;
-    let mut genome = Vec::with_capacity(size);
-    for i in 0..size {
-        if i < root {
+/// A red bottom row on black
+pub fn create_runner(width: usize, height: usize) -> Genome<RGB> {
+    let mut genome = Vec::with_capacity(width * height);
+    for i in 0..width * height {
+        if i < width {
             genome.push(RGB { r: 255, g: 0, b: 0 });
         }
         else {
             genome.push(RGB { r: 0, g: 0, b: 0 });
         }
     }
-    genome
+    Genome::new(genome, width, height)
 }
 
-pub fn create_black(size: usize) -> Genome<RGB> {
-    let mut genome = Vec::with_capacity(size);
-    for i in 0..size {
-        genome.push(RGB { r: 0, g: 0, b: 0 });
-    }
-    genome
+pub fn create_black(width: usize, height: usize) -> Genome<RGB> {
+    Genome::filled(RGB { r: 0, g: 0, b: 0 }, width, height)
 }
@@ -0,0 +1,156 @@
+use rand::Rng;
+
+use crate::{
+    evolution::gene::{Compare, Genome},
+    util::rng,
+};
+
+/// A group of genomes which are closer to its representative than the compatibility threshold
+#[derive(Debug, Clone)]
+pub struct Species<T> {
+    pub id: usize,
+    /// a random member of the previous generation, new genomes are compared against it
+    pub representative: Genome<T>,
+    /// indices into the population
+    pub members: Vec<usize>,
+    /// generations since the species appeared
+    pub age: usize,
+    /// the lowest cost any member ever reached
+    pub best: f32,
+    /// generations since best last improved
+    pub stagnant: usize,
+    /// amount of children during the last generation
+    pub offspring: usize,
+}
+
+impl<T> Species<T> {
+    /// A hue which stays the same for the whole life of the species
+    pub fn hue(&self) -> f32 {
+        // the golden ratio spreads consecutive ids around the color wheel
+        (self.id as f32 * 0.618_034).fract()
+    }
+}
+
+/// Groups a population into species, NEAT style
+/// Distances are 1 - Compare similarity
+pub struct Speciation<T> {
+    pub species: Vec<Species<T>>,
+    /// the index into species of every creature
+    pub labels: Vec<usize>,
+    next_id: usize,
+}
+
+impl<T> Speciation<T>
+where
+    T: Compare + Clone,
+{
+    pub fn new() -> Self {
+        Speciation {
+            species: Vec::new(),
+            labels: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Forgets every species, e.g. when speciation is turned off
+    pub fn clear(&mut self) {
+        self.species.clear();
+        self.labels.clear();
+    }
+
+    /// Sorts every genome into the first species whose representative is closer than threshold
+    /// Genomes without a species found a new one, species without members die out
+    pub fn speciate(&mut self, genomes: &[&Genome<T>], costs: &[f32], threshold: f32) {
+        for species in self.species.iter_mut() {
+            species.members.clear();
+        }
+        for (i, &genome) in genomes.iter().enumerate() {
+            let species = self
+                .species
+                .iter_mut()
+                .find(|s| 1.0 - genome.compare(&s.representative) < threshold);
+            match species {
+                Some(species) => species.members.push(i),
+                None => {
+                    self.species.push(Species {
+                        id: self.next_id,
+                        representative: genome.clone(),
+                        members: vec![i],
+                        age: 0,
+                        best: f32::MAX,
+                        stagnant: 0,
+                        offspring: 0,
+                    });
+                    self.next_id += 1;
+                }
+            }
+        }
+        self.species.retain(|s| !s.members.is_empty());
+
+        let mut rng = rng();
+        self.labels = vec![0; genomes.len()];
+        for (index, species) in self.species.iter_mut().enumerate() {
+            species.age += 1;
+            let best = species.members.iter().map(|&i| costs[i]).fold(f32::MAX, f32::min);
+            if best < species.best {
+                species.best = best;
+                species.stagnant = 0;
+            } else {
+                species.stagnant += 1;
+            }
+            let representative = species.members[rng.gen_range(0..species.members.len())];
+            species.representative = genomes[representative].clone();
+            for &i in species.members.iter() {
+                self.labels[i] = index;
+            }
+        }
+    }
+
+    /// Species relative costs, every creature shares its fitness with the members of its species
+    pub fn adjust(&self, costs: &[f32]) -> Vec<f32> {
+        // costs can be negative, so sharing happens on a positive fitness
+        let worst = costs.iter().cloned().fold(f32::MIN, f32::max);
+        costs
+            .iter()
+            .enumerate()
+            .map(|(i, &cost)| {
+                let size = self.species[self.labels[i]].members.len();
+                -(worst - cost + f32::EPSILON) / size as f32
+            })
+            .collect()
+    }
+
+    /// Splits size children among the species in proportion to their summed adjusted fitness
+    /// Species which did not improve for stagnation generations get none, unless they hold the best creature
+    pub fn quotas(&mut self, adjusted: &[f32], size: usize, stagnation: usize) -> Vec<usize> {
+        let champion = (0..adjusted.len())
+            .min_by(|&a, &b| adjusted[a].total_cmp(&adjusted[b]))
+            .map(|i| self.labels[i]);
+        let shares: Vec<f32> = self
+            .species
+            .iter()
+            .enumerate()
+            .map(|(index, species)| {
+                if species.stagnant >= stagnation && Some(index) != champion {
+                    return 0.0;
+                }
+                species.members.iter().map(|&i| -adjusted[i]).sum()
+            })
+            .collect();
+        let total: f32 = shares.iter().sum();
+
+        // largest remainder method, so the quotas add up to size
+        let exact: Vec<f32> = shares.iter().map(|&s| s / total * size as f32).collect();
+        let mut quotas: Vec<usize> = exact.iter().map(|&e| e as usize).collect();
+        let mut order: Vec<usize> = (0..quotas.len()).collect();
+        order.sort_unstable_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+        let missing = size - quotas.iter().sum::<usize>();
+        for &index in order.iter().cycle().take(missing) {
+            quotas[index] += 1;
+        }
+        for (species, &quota) in self.species.iter_mut().zip(quotas.iter()) {
+            species.offspring = quota;
+        }
+        quotas
+    }
+}
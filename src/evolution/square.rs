@@ -1,9 +1,16 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::usize;
 
-use crate::util::Create;
+use rand::Rng;
+
+use crate::util::{rng, Create};
 use super::gene::Genome;
 
 /// A Quadrant defines a specific subset of the Square
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Quadrant {
     TopTriangularQuadrant,
     BottomTriangularQuadrant,
@@ -11,94 +18,399 @@ pub enum Quadrant {
     LeftTriangularQuadrant,
 }
 
-pub trait Square<T: Copy> {
-    fn side_length(&self) -> usize;
-    fn get(&self, at: (usize, usize)) -> &T;
-    fn set(&mut self, at: (usize, usize), value: T);
+/// Any subset of the genes of a Square, coordinates are in genes with (0, 0) at the bottom left
+#[derive(Debug, Clone)]
+pub enum Region {
+    Quadrant(Quadrant),
+    /// the bottom left corner and the size, clipped to the grid
+    Rect { x: usize, y: usize, width: usize, height: usize },
+    /// every gene whose center is at most radius away from (x, y)
+    Circle { x: f32, y: f32, radius: f32 },
+    /// every gene whose center is between inner and outer away from (x, y)
+    Ring { x: f32, y: f32, inner: f32, outer: f32 },
+    Row(usize),
+    Column(usize),
+    /// one flag per gene, row by row like the genome itself
+    Mask(Vec<bool>),
+}
 
-    fn get_quadrant(&self, quadrant: Quadrant) -> Vec<&T> {
-        let indices = Self::get_quadrant_indices(quadrant, self.side_length());
-        let mut result = Vec::with_capacity(indices.len());
-        for index in indices.iter() {
-            result.push(self.get(*index));
-        }
-        result
-    }
-    
-    fn set_quadrant(&mut self, quadrant: Quadrant, value: T) {
-        let indices = Self::get_quadrant_indices(quadrant, self.side_length());
-        for index in indices.iter() {
-            self.set(*index, value);
-        }
-    }
-    
-    fn get_quadrant_indices(quadrant: Quadrant, side_length: usize) -> Vec<(usize, usize)> {
-        let half = side_length / 2;
-        let mut triangle = Vec::with_capacity(half);
-        for i in 0..half {
-            let mut row = Vec::with_capacity(side_length - 2*i);
-            for j in (i)..(side_length - i) {
-               row.push(j);
+impl Region {
+    pub fn contains(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        let distance = |cx: f32, cy: f32| ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt();
+        match self {
+            Region::Quadrant(quadrant) => quadrant_contains(*quadrant, x, y, width, height),
+            Region::Rect { x: left, y: bottom, width: w, height: h } => {
+                (*left..left + w).contains(&x) && (*bottom..bottom + h).contains(&y)
             }
-            triangle.push(row)
+            Region::Circle { x: cx, y: cy, radius } => distance(*cx, *cy) <= *radius,
+            Region::Ring { x: cx, y: cy, inner, outer } => (*inner..=*outer).contains(&distance(*cx, *cy)),
+            Region::Row(row) => y == *row,
+            Region::Column(column) => x == *column,
+            Region::Mask(mask) => mask.get(y * width + x).copied().unwrap_or(false),
         }
+    }
 
-        match quadrant {
-            Quadrant::TopTriangularQuadrant => {
-                let mut result = Vec::with_capacity(half * half + 2*half);
-                for (i, row) in triangle.iter().enumerate() {
-                    for j in row.iter() {
-                        result.push((*j, side_length - 1 - i));
-                    }
-                }
-                result
-            },
-            Quadrant::BottomTriangularQuadrant => {
-                let mut result = Vec::with_capacity(half * half + 2*half);
-                for (i, row) in triangle.iter().enumerate() {
-                    for j in row.iter() {
-                        result.push((*j, i));
-                    }
-                }
-                result
+    /// The genome indices inside the region, in genome order
+    pub fn indices(&self, width: usize, height: usize) -> Vec<usize> {
+        (0..width * height)
+            .filter(|&i| self.contains(i % width, i / width, width, height))
+            .collect()
+    }
+
+    /// A random rectangle, circle, ring, row or column which lies at least partly inside the grid
+    pub fn random(width: usize, height: usize) -> Region {
+        let mut rng = rng();
+        let (x, y) = (rng.gen_range(0..width), rng.gen_range(0..height));
+        let extent = width.max(height) as f32;
+        match rng.gen_range(0..5) {
+            0 => Region::Rect {
+                x,
+                y,
+                width: rng.gen_range(1..=width - x),
+                height: rng.gen_range(1..=height - y),
             },
-            Quadrant::RightTriangularQuadrant => {
-                let mut result = Vec::with_capacity(half * half + 2*half);
-                for (i, row) in triangle.iter().enumerate() {
-                    for j in row.iter() {
-                        result.push((side_length - 1 - i, *j));
-                    }
-                }
-                result
+            1 => Region::Circle {
+                x: x as f32,
+                y: y as f32,
+                radius: rng.gen_range(0.0..extent / 2.0),
             },
-            Quadrant::LeftTriangularQuadrant => {
-                let mut result = Vec::with_capacity(half * half + 2*half);
-                for (i, row) in triangle.iter().enumerate() {
-                    for j in row.iter() {
-                        result.push((i, *j));
-                    }
+            2 => {
+                let inner = rng.gen_range(0.0..extent / 2.0);
+                Region::Ring {
+                    x: x as f32,
+                    y: y as f32,
+                    inner,
+                    outer: inner + rng.gen_range(1.0..extent / 4.0 + 1.0),
                 }
-                result
+            }
+            3 => Region::Row(y),
+            _ => Region::Column(x),
+        }
+    }
+}
+
+// Regions are cache keys, floats are compared by their bits
+impl PartialEq for Region {
+    fn eq(&self, other: &Self) -> bool {
+        let same = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits());
+        match (self, other) {
+            (Region::Quadrant(a), Region::Quadrant(b)) => a == b,
+            (
+                Region::Rect { x, y, width, height },
+                Region::Rect { x: x2, y: y2, width: width2, height: height2 },
+            ) => (x, y, width, height) == (x2, y2, width2, height2),
+            (Region::Circle { x, y, radius }, Region::Circle { x: x2, y: y2, radius: radius2 }) => {
+                same(&[*x, *y, *radius], &[*x2, *y2, *radius2])
+            }
+            (
+                Region::Ring { x, y, inner, outer },
+                Region::Ring { x: x2, y: y2, inner: inner2, outer: outer2 },
+            ) => same(&[*x, *y, *inner, *outer], &[*x2, *y2, *inner2, *outer2]),
+            (Region::Row(a), Region::Row(b)) => a == b,
+            (Region::Column(a), Region::Column(b)) => a == b,
+            (Region::Mask(a), Region::Mask(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Region {}
+
+impl Hash for Region {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Region::Quadrant(quadrant) => quadrant.hash(state),
+            Region::Rect { x, y, width, height } => (x, y, width, height).hash(state),
+            Region::Circle { x, y, radius } => (x.to_bits(), y.to_bits(), radius.to_bits()).hash(state),
+            Region::Ring { x, y, inner, outer } => (x.to_bits(), y.to_bits(), inner.to_bits(), outer.to_bits()).hash(state),
+            Region::Row(row) => row.hash(state),
+            Region::Column(column) => column.hash(state),
+            Region::Mask(mask) => mask.hash(state),
+        }
+    }
+}
+
+/// The triangles between the diagonals of the grid, the diagonals belong to both neighbours
+fn quadrant_contains(quadrant: Quadrant, x: usize, y: usize, width: usize, height: usize) -> bool {
+    // distances to the edges, scaled by the other side to compare them on a rectangle
+    let (left, right) = (x * height, (width - 1 - x) * height);
+    let (bottom, top) = (y * width, (height - 1 - y) * width);
+    match quadrant {
+        Quadrant::TopTriangularQuadrant => top <= left && top <= right && 2 * (height - 1 - y) < height - 1,
+        Quadrant::BottomTriangularQuadrant => bottom <= left && bottom <= right && 2 * y < height - 1,
+        Quadrant::RightTriangularQuadrant => right <= bottom && right <= top && 2 * (width - 1 - x) < width - 1,
+        Quadrant::LeftTriangularQuadrant => left <= bottom && left <= top && 2 * x < width - 1,
+    }
+}
+
+/// The cache forgets every table once it holds this many
+const CACHE_LIMIT: usize = 4096;
+
+// tables which are asked for often, e.g. the quadrants of every blob in every frame, are only computed once
+thread_local! {
+    static REGIONS: RefCell<HashMap<(Region, usize, usize), Rc<[usize]>>> = RefCell::new(HashMap::new());
+}
+
+/// Like Region::indices, but remembers the result
+pub fn region_indices(region: &Region, width: usize, height: usize) -> Rc<[usize]> {
+    REGIONS.with(|regions| {
+        let mut regions = regions.borrow_mut();
+        let key = (region.clone(), width, height);
+        if let Some(indices) = regions.get(&key) {
+            return indices.clone();
+        }
+        // random regions would otherwise grow the cache forever
+        if regions.len() >= CACHE_LIMIT {
+            regions.clear();
+        }
+        let indices: Rc<[usize]> = region.indices(width, height).into();
+        regions.insert(key, indices.clone());
+        indices
+    })
+}
+
+/// Which mirror images make up a genome, only the genes of the fundamental domain are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    None,
+    /// the right half mirrors the left half
+    Bilateral,
+    /// the bottom left quarter is mirrored left to right and bottom to top
+    FourFold,
+    /// like FourFold, and the quarter is also mirrored along its diagonal
+    /// non square grids fall back to FourFold
+    EightFold,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 4] = [Symmetry::None, Symmetry::Bilateral, Symmetry::FourFold, Symmetry::EightFold];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symmetry::None => "none",
+            Symmetry::Bilateral => "bilateral",
+            Symmetry::FourFold => "four-fold",
+            Symmetry::EightFold => "eight-fold",
+        }
+    }
+
+    /// The symmetry which a width * height grid actually gets
+    fn on(&self, width: usize, height: usize) -> Symmetry {
+        match self {
+            Symmetry::EightFold if width != height => Symmetry::FourFold,
+            symmetry => *symmetry,
+        }
+    }
+
+    /// The amount of stored genes of a width * height grid
+    pub fn domain_size(&self, width: usize, height: usize) -> usize {
+        let (half_width, half_height) = ((width + 1) / 2, (height + 1) / 2);
+        match self.on(width, height) {
+            Symmetry::None => width * height,
+            Symmetry::Bilateral => half_width * height,
+            Symmetry::FourFold => half_width * half_height,
+            Symmetry::EightFold => half_width * (half_width + 1) / 2,
+        }
+    }
+
+    /// The index of the stored gene which is shown at (x, y)
+    pub fn fold(&self, x: usize, y: usize, width: usize, height: usize) -> usize {
+        let mirror = |v: usize, side: usize| v.min(side - 1 - v);
+        let half_width = (width + 1) / 2;
+        match self.on(width, height) {
+            Symmetry::None => y * width + x,
+            Symmetry::Bilateral => y * half_width + mirror(x, width),
+            Symmetry::FourFold => mirror(y, height) * half_width + mirror(x, width),
+            Symmetry::EightFold => {
+                let (x, y) = (mirror(x, width), mirror(y, height));
+                let (low, high) = (x.min(y), x.max(y));
+                // the triangle below the diagonal, row by row
+                high * (high + 1) / 2 + low
+            }
+        }
+    }
+}
+
+thread_local! {
+    static DOMAINS: RefCell<HashMap<(Symmetry, usize, usize), Rc<[(usize, usize)]>>> = RefCell::new(HashMap::new());
+}
+
+/// For every stored gene the bottom left most position of the grid which shows it, remembers the result
+pub fn domain_positions(symmetry: Symmetry, width: usize, height: usize) -> Rc<[(usize, usize)]> {
+    DOMAINS.with(|domains| {
+        let mut domains = domains.borrow_mut();
+        if let Some(positions) = domains.get(&(symmetry, width, height)) {
+            return positions.clone();
+        }
+        if domains.len() >= CACHE_LIMIT {
+            domains.clear();
+        }
+        let mut positions = vec![None; symmetry.domain_size(width, height)];
+        for i in 0..width * height {
+            let (x, y) = (i % width, i / width);
+            positions[symmetry.fold(x, y, width, height)].get_or_insert((x, y));
+        }
+        let positions: Rc<[(usize, usize)]> = positions.into_iter().map(Option::unwrap).collect();
+        domains.insert((symmetry, width, height), positions.clone());
+        positions
+    })
+}
+
+/// Whole genome rearrangements, rotations are counter clockwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// left and right swap
+    MirrorX,
+    /// top and bottom swap
+    MirrorY,
+    /// rows become columns
+    Transpose,
+    /// moves every gene, genes leaving the grid come back on the other side
+    Shift { x: isize, y: isize },
+}
+
+impl Transform {
+    pub fn random() -> Transform {
+        let mut rng = rng();
+        match rng.gen_range(0..7) {
+            0 => Transform::Rotate90,
+            1 => Transform::Rotate180,
+            2 => Transform::Rotate270,
+            3 => Transform::MirrorX,
+            4 => Transform::MirrorY,
+            5 => Transform::Transpose,
+            _ => Transform::Shift {
+                x: rng.gen_range(-2..=2),
+                y: rng.gen_range(-2..=2),
             },
         }
     }
+
+    /// A random transform which keeps the symmetry, see preserves
+    pub fn random_for(symmetry: Symmetry) -> Transform {
+        loop {
+            let transform = Transform::random();
+            if transform.preserves(symmetry) {
+                return transform;
+            }
+        }
+    }
+
+    /// Whether a grid with the symmetry still has it after the transform
+    /// Only then folding the transformed grid keeps every gene, otherwise half of them are lost
+    pub fn preserves(&self, symmetry: Symmetry) -> bool {
+        match (self, symmetry) {
+            (_, Symmetry::None) => true,
+            // the mirror axis of a bilateral grid has to stay in the middle and upright
+            (Transform::Shift { x, .. }, Symmetry::Bilateral) => *x == 0,
+            (Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose, Symmetry::Bilateral) => false,
+            // rotations and mirrors map the axes of four- and eight-fold grids onto each other
+            (Transform::Shift { x, y }, _) => *x == 0 && *y == 0,
+            _ => true,
+        }
+    }
+
+    /// The dimensions of a width * height grid after the transform
+    pub fn dimensions(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Rotate90 | Transform::Rotate270 | Transform::Transpose => (height, width),
+            _ => (width, height),
+        }
+    }
+
+    /// The position in the original width * height grid which ends up at (x, y)
+    pub fn source(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match *self {
+            Transform::Rotate90 => (y, height - 1 - x),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Rotate270 => (width - 1 - y, x),
+            Transform::MirrorX => (width - 1 - x, y),
+            Transform::MirrorY => (x, height - 1 - y),
+            Transform::Transpose => (y, x),
+            Transform::Shift { x: dx, y: dy } => (
+                (x as isize - dx).rem_euclid(width as isize) as usize,
+                (y as isize - dy).rem_euclid(height as isize) as usize,
+            ),
+        }
+    }
+}
+
+/// A rectangular grid of genes, (0, 0) is the bottom left corner
+pub trait Square<T: Copy> {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn get(&self, at: (usize, usize)) -> &T;
+    fn set(&mut self, at: (usize, usize), value: T);
+
+    /// The cached indices of a region of this grid
+    fn region_indices(&self, region: &Region) -> Rc<[usize]> {
+        region_indices(region, self.width(), self.height())
+    }
+
+    fn get_region(&self, region: &Region) -> Vec<&T> {
+        let width = self.width();
+        self.region_indices(region)
+            .iter()
+            .map(|&i| self.get((i % width, i / width)))
+            .collect()
+    }
+
+    fn set_region(&mut self, region: &Region, value: T) {
+        let width = self.width();
+        for &i in self.region_indices(region).iter() {
+            self.set((i % width, i / width), value);
+        }
+    }
+
+    fn get_quadrant(&self, quadrant: Quadrant) -> Vec<&T> {
+        self.get_region(&Region::Quadrant(quadrant))
+    }
+
+    fn set_quadrant(&mut self, quadrant: Quadrant, value: T) {
+        self.set_region(&Region::Quadrant(quadrant), value);
+    }
 }
 
 impl<T: Create + Clone + Copy> Square<T> for Genome<T> {
 
-    fn side_length(&self) -> usize {
-        (self.len() as f32).sqrt() as usize
+    fn width(&self) -> usize {
+        self.dimensions().0
     }
 
+    fn height(&self) -> usize {
+        self.dimensions().1
+    }
+
+    /// Symmetric genomes show the same stored gene at several positions
     fn get(&self, at: (usize, usize)) -> &T {
         let (x, y) = at;
-        let width = self.side_length();
-        &self[y*width + x]
+        &self[self.gene_index(x, y)]
     }
-    
+
+    /// Changes every mirror image of the position as well
     fn set(&mut self, at: (usize, usize), value: T) {
         let (x, y) = at;
-        let width = self.side_length();
-        self[y*width + x] = value;
+        let index = self.gene_index(x, y);
+        self[index] = value;
+    }
+}
+
+impl<T: Copy> Genome<T> {
+    /// Symmetric genomes are transformed as a whole and folded again, so they keep their symmetry
+    /// Transforms which do not preserve the symmetry lose the genes of the mirror images that are folded away
+    pub fn transformed(&self, transform: Transform) -> Genome<T> {
+        let (width, height) = self.dimensions();
+        let (new_width, new_height) = transform.dimensions(width, height);
+        let genes = (0..new_width * new_height)
+            .map(|i| {
+                let (x, y) = transform.source(i % new_width, i / new_width, width, height);
+                self[self.gene_index(x, y)]
+            })
+            .collect();
+        Genome::new(genes, new_width, new_height).folded(self.symmetry())
     }
-}
\ No newline at end of file
+}
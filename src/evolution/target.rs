@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use nannou::image::{self, imageops::FilterType, ImageError};
+
+use crate::evolution::{
+    blob::RGB,
+    gene::{Compare, Genome},
+};
+
+/// A Distance defines how similar a Genome<RGB> looks compared to a reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distance {
+    /// The channel-sum difference of RGB::compare
+    Channel,
+    /// Euclidean distance inside the RGB cube
+    Euclidean,
+    /// CIE76 color difference (ΔE) inside the Lab color space
+    Lab,
+    /// Structural similarity of the luminance, computed on small windows
+    Structural,
+}
+
+impl Distance {
+    pub const ALL: [Distance; 4] = [
+        Distance::Channel,
+        Distance::Euclidean,
+        Distance::Lab,
+        Distance::Structural,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Distance::Channel => "channel",
+            Distance::Euclidean => "euclidean",
+            Distance::Lab => "lab",
+            Distance::Structural => "structural",
+        }
+    }
+
+    /// rates similarity in percent, just like Compare
+    /// Genomes of different sizes are aligned on the center, just like in Compare
+    /// Symmetric genomes are rated on their whole grid
+    pub fn similarity(&self, genome: &Genome<RGB>, reference: &Genome<RGB>) -> f32 {
+        let (genome, reference) = (genome.expanded(), reference.expanded());
+        let (genome, reference) = (&*genome, &*reference);
+        match self {
+            Distance::Channel => genome.compare(reference),
+            Distance::Euclidean => mean_over_genes(genome, reference, euclidean),
+            Distance::Lab => mean_over_genes(genome, reference, delta_e),
+            Distance::Structural => structural(genome, reference),
+        }
+    }
+}
+
+/// Loads a PNG/PPM image and downsamples it to a Genome<RGB> of width * height
+/// The bottom row of the image ends up at the start of the genome, just like it is drawn
+pub fn load_target<P: AsRef<Path>>(path: P, width: usize, height: usize) -> Result<Genome<RGB>, ImageError> {
+    let image = image::open(path)?;
+    Ok(downsample(&image.to_rgb8(), width, height))
+}
+
+pub fn downsample(image: &image::RgbImage, width: usize, height: usize) -> Genome<RGB> {
+    let resized = image::imageops::resize(image, width as u32, height as u32, FilterType::Triangle);
+    let mut genome = Vec::with_capacity(width * height);
+    for y in (0..height as u32).rev() {
+        for x in 0..width as u32 {
+            let [r, g, b] = resized.get_pixel(x, y).0;
+            genome.push(RGB { r, g, b });
+        }
+    }
+    Genome::new(genome, width, height)
+}
+
+fn mean_over_genes(genome: &Genome<RGB>, reference: &Genome<RGB>, similarity: fn(&RGB, &RGB) -> f32) -> f32 {
+    let norm = genome.len().max(reference.len()) as f32;
+    genome
+        .iter()
+        .enumerate()
+        .filter_map(|(at, a)| genome.aligned(reference, at).map(|b| similarity(a, b)))
+        .sum::<f32>()
+        / norm
+}
+
+fn euclidean(a: &RGB, b: &RGB) -> f32 {
+    let norm = (3.0 * 255.0 * 255.0 as f32).sqrt();
+    let r_diff = a.r as f32 - b.r as f32;
+    let g_diff = a.g as f32 - b.g as f32;
+    let b_diff = a.b as f32 - b.b as f32;
+    1.0 - (r_diff * r_diff + g_diff * g_diff + b_diff * b_diff).sqrt() / norm
+}
+
+fn delta_e(a: &RGB, b: &RGB) -> f32 {
+    // a ΔE of 100 is roughly the distance between black and white
+    let norm = 100.0;
+    let (l1, a1, b1) = to_lab(a);
+    let (l2, a2, b2) = to_lab(b);
+    let distance = ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt();
+    (1.0 - distance / norm).max(0.0)
+}
+
+/// sRGB -> linear RGB -> XYZ (D65) -> CIE Lab
+fn to_lab(color: &RGB) -> (f32, f32, f32) {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(color.r), linear(color.g), linear(color.b));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+fn luminance(color: &RGB) -> f32 {
+    0.299 * color.r as f32 + 0.587 * color.g as f32 + 0.114 * color.b as f32
+}
+
+/// Mean SSIM of the luminance over all windows of size 3x3, mapped from [-1, 1] to [0, 1]
+fn structural(genome: &Genome<RGB>, reference: &Genome<RGB>) -> f32 {
+    let (width, height) = genome.dimensions();
+    let window = 3.min(width).min(height);
+    // stabilizing constants of the SSIM paper for a dynamic range of 255
+    let c1 = (0.01 * 255.0 as f32).powi(2);
+    let c2 = (0.03 * 255.0 as f32).powi(2);
+
+    let a: Vec<f32> = genome.iter().map(luminance).collect();
+    // the reference is read through the grid of the genome, missing genes are black
+    let b: Vec<f32> = (0..genome.len())
+        .map(|at| genome.aligned(reference, at).map_or(0.0, luminance))
+        .collect();
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in 0..=(height - window) {
+        for x in 0..=(width - window) {
+            let indices: Vec<usize> = (0..window * window)
+                .map(|i| (y + i / window) * width + x + i % window)
+                .collect();
+            let n = indices.len() as f32;
+            let mean_a = indices.iter().map(|&i| a[i]).sum::<f32>() / n;
+            let mean_b = indices.iter().map(|&i| b[i]).sum::<f32>() / n;
+            let mut var_a = 0.0;
+            let mut var_b = 0.0;
+            let mut covariance = 0.0;
+            for &i in indices.iter() {
+                var_a += (a[i] - mean_a).powi(2);
+                var_b += (b[i] - mean_b).powi(2);
+                covariance += (a[i] - mean_a) * (b[i] - mean_b);
+            }
+            var_a /= n;
+            var_b /= n;
+            covariance /= n;
+
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+    (total / windows as f32 + 1.0) / 2.0
+}
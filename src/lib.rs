@@ -0,0 +1,34 @@
+pub mod camera;
+pub mod evolution;
+pub mod logging;
+pub mod simulation;
+pub mod statistics;
+pub mod util;
+
+/// nannou
+use nannou::prelude::*;
+
+/// internal modules
+use camera::Camera;
+use evolution::blob::{DrawMode, Overlay};
+use simulation::atlas::GenomeAtlas;
+
+/// What drawing depends on besides the thing which is drawn
+pub struct View<'a> {
+    pub camera: &'a Camera,
+    pub draw_mode: DrawMode,
+    pub overlay: Overlay,
+    /// one atlas per island, empty unless drawing textures
+    pub atlases: &'a [GenomeAtlas],
+}
+
+impl View<'_> {
+    pub fn transform(&self, v: Vec2) -> Vec2 {
+        self.camera.transform(v)
+    }
+}
+
+pub trait Nannou {
+    fn draw(&self, draw: &Draw, view: &View);
+    fn update(&mut self);
+}
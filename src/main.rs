@@ -1,95 +1,1055 @@
-mod evolution;
-mod simulation;
-mod statistics;
-mod util;
+mod cli;
+mod replay;
+
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 /// nannou
 use nannou::prelude::*;
 use nannou_egui::{self, egui, Egui};
 
 /// internal modules
-use simulation::controller::SimpleBlobController;
-use util::{distribute_uniformly, Create};
+use genetic_blobs::{camera, evolution, logging, simulation, statistics, util, Nannou, View};
+use genetic_blobs::{debug, error, info, warn};
+use camera::{blob_bounds, population_bounds, Camera};
+use cli::{CliError, Command, Options};
+use evolution::{
+    blob::{DrawMode, Overlay},
+    builder::{GenomeBuilder, Initializer, PopulationBuilder, Template},
+    development::Encoding,
+    diversity::Preservation,
+    error::EvolutionError,
+    gene::{Crossover, EvolveSettings},
+    placement::Placement,
+    square::Symmetry,
+    target::Distance,
+};
+use simulation::{
+    controller::{ControllerBuilder, Selection, SimpleBlobController, MAX_RATING},
+    atlas::GenomeAtlas,
+    export::Exporter,
+    headless::Headless,
+    islands::{Archipelago, Migration, Topology},
+    recorder::{Recorder, RecordingConfig},
+    scheduler::{Scheduler, Ticks},
+    snapshot::{self, SavedIsland},
+    termination::{RunOutcome, Termination},
+};
+use statistics::metrics::{Diversity, MetricsSink};
 
 /// STARTING WINDOW SIZE
 const WIDTH: f32 = 640.0;
 const HEIGHT: f32 = 360.0;
+/// width and height of the initial genomes
+const GENOME: (usize, usize) = (12, 12);
 
-trait Nannou {
-    fn draw(&self, draw: &Draw, model: &Model);
-    fn update(&mut self);
-}
 struct Model {
-    center: Point2,
-    zoom: f32,
-    controller: SimpleBlobController,
+    camera: Camera,
+    draw_mode: DrawMode,
+    overlay: Overlay,
+    /// one atlas per island, only kept while drawing textures
+    atlases: Vec<GenomeAtlas>,
+    archipelago: Archipelago,
     egui: Egui,
     count: u32,
     window_id: WindowId,
+    target_path: String,
+    distance: Distance,
+    target_status: String,
+    exporter: Exporter,
+    export_status: String,
+    recorder: Recorder,
+    scheduler: Scheduler,
+    ticks: Ticks,
+    metrics_path: String,
+    metrics: Option<MetricsSink>,
+    termination: Termination,
+    /// set when the last run ended, the scheduler is paused until the run restarts
+    outcome: Option<RunOutcome>,
+    /// the diversity of the selected island, measured once per island and generation
+    diversity: Option<((usize, usize), Diversity)>,
 }
 
 impl Model {
     fn transform(&self, v: Vec2) -> Vec2 {
-        (v - self.center) * self.zoom
+        self.camera.transform(v)
+    }
+
+    fn view(&self) -> View<'_> {
+        View {
+            camera: &self.camera,
+            draw_mode: self.draw_mode,
+            overlay: self.overlay,
+            atlases: &self.atlases,
+        }
     }
 }
 
 fn main() {
-    nannou::app(model).update(update).run();
+    logging::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = cli::parse(&args).and_then(|(command, options)| start(command, &options));
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        let usage = !matches!(
+            e,
+            CliError::Read { .. } | CliError::Write { .. } | CliError::Evolution(_) | CliError::Run(_)
+        );
+        if usage {
+            eprintln!("evolution --help lists the commands and their flags");
+        }
+        std::process::exit(2);
+    }
+}
+
+/// Validates the options of a command and runs it
+fn start(command: Command, options: &Options) -> Result<(), CliError> {
+    if let Some(seed) = options.seed()? {
+        util::seed(seed);
+    }
+    match command {
+        Command::Help(command) => print!("{}", cli::usage(command.as_deref())),
+        Command::Run => {
+            let viewer = Viewer {
+                world: World::from_options(options)?,
+                generations: options.generations()?.filter(|&n| n > 0),
+                out: options.out().unwrap_or_else(|| "export".into()),
+            };
+            VIEWER.get_or_init(|| viewer);
+            nannou::app(model).update(update).run();
+        }
+        Command::Headless => headless(options, None)?,
+        Command::Resume(path) => {
+            let saved = snapshot::read(&path).map_err(|error| CliError::Read { path, error })?;
+            headless(options, Some(saved))?;
+        }
+        Command::ExportImages(path) => export_images(options, path)?,
+        Command::Bench => bench(options)?,
+        Command::Replay(path) => replay::start(path, options)?,
+    }
+    Ok(())
+}
+
+/// How the archipelago of every command is built
+#[derive(Debug, Clone)]
+struct World {
+    islands: usize,
+    migration: Migration,
+    genome: (usize, usize),
+    /// blobs per island
+    blobs: usize,
+    template: Template,
+    initializer: Initializer,
+    placement: Placement,
+    /// a factor on the distance between blobs
+    spacing: f32,
+    symmetry: Option<Symmetry>,
+    encoding: Option<Encoding>,
+    preservation: Option<Preservation>,
+}
+
+impl World {
+    fn from_options(options: &Options) -> Result<World, CliError> {
+        let whole = "a whole number";
+        let mut migration = Migration::default();
+        migration.every = options.value("migrate-every", whole)?.unwrap_or(migration.every);
+        migration.amount = options.value("migrants", whole)?.unwrap_or(migration.amount);
+        migration.topology = options
+            .choice("topology", Topology::ALL, Topology::name, "ring, full or random")?
+            .unwrap_or(migration.topology);
+        let preservation = options.choice(
+            "preservation",
+            Preservation::all(),
+            Preservation::flag,
+            "none, sharing, crowding, niching or speciation",
+        )?;
+        let genome = options.dimensions("genome")?.unwrap_or(GENOME);
+        let steps: Option<usize> = options.value("steps", whole)?;
+        let encoding = match options.choice("encoding", ["direct", "automaton"], |e| e, "direct or automaton")? {
+            Some("automaton") => {
+                let mut encoding = Encoding::automaton(genome.0, genome.1);
+                if let (Encoding::Automaton { steps, .. }, Some(n)) = (&mut encoding, steps) {
+                    *steps = n;
+                }
+                Some(encoding)
+            }
+            Some(_) => Some(Encoding::Direct),
+            None => None,
+        };
+        let templates = [Template::Random, Template::Black, Template::Runner];
+        Ok(World {
+            islands: options.value("islands", whole)?.unwrap_or(1usize).max(1),
+            migration,
+            genome,
+            blobs: options.value("blobs", whole)?.unwrap_or(32),
+            template: options
+                .choice("template", templates, Template::name, "random, black or runner")?
+                .unwrap_or(Template::Runner),
+            initializer: initializer(options)?,
+            placement: options
+                .choice("placement", Placement::ALL, Placement::name, "rings, poisson, hex, square, random or clustered")?
+                .unwrap_or(Placement::Rings),
+            spacing: options.value("spacing", "a factor of at least 1")?.unwrap_or(1.0),
+            symmetry: options.choice("symmetry", Symmetry::ALL, Symmetry::name, "none, bilateral, four-fold or eight-fold")?,
+            encoding,
+            preservation,
+        })
+    }
+
+    fn builders(&self) -> (PopulationBuilder, ControllerBuilder) {
+        let (width, height) = self.genome;
+        let genome = GenomeBuilder::new()
+            .dimensions(width, height)
+            .template(self.template)
+            .symmetry(self.symmetry.unwrap_or(Symmetry::None));
+        let population = PopulationBuilder::new()
+            .size(self.blobs)
+            .genome(genome)
+            .initializer(self.initializer.clone())
+            .placement(self.placement)
+            .spacing(self.spacing);
+        let mut settings = EvolveSettings::default();
+        settings.preservation = self.preservation.unwrap_or(settings.preservation);
+        let controller = ControllerBuilder::new()
+            .encoding(self.encoding.unwrap_or(Encoding::Direct))
+            .settings(settings);
+        (population, controller)
+    }
+
+    fn create(&self) -> Result<Archipelago, EvolutionError> {
+        let (population, controller) = self.builders();
+        create_archipelago(vec![(population, controller); self.islands], self.migration)
+    }
+
+    /// A single island around offset
+    fn island(&self, offset: Vec2) -> Result<SimpleBlobController, EvolutionError> {
+        let (population, controller) = self.builders();
+        controller.population(population.offset(offset)).build()
+    }
+
+    /// One island per saved island, with as many blobs as it saved and as large as the grids its genomes grow into
+    /// A given encoding or symmetry replaces the saved one
+    fn load(&self, saved: Vec<SavedIsland>) -> Result<Archipelago, EvolutionError> {
+        if self.islands != saved.len() && self.islands != 1 {
+            warn!("the snapshot has {} islands, ignoring --islands {}", saved.len(), self.islands);
+        }
+        let builders = saved
+            .iter()
+            .map(|saved| {
+                World {
+                    islands: 1,
+                    blobs: saved.genomes.len(),
+                    genome: match saved.encoding {
+                        Encoding::Direct => saved.genomes[0].dimensions(),
+                        Encoding::Automaton { width, height, .. } => (width, height),
+                    },
+                    symmetry: None,
+                    encoding: None,
+                    ..self.clone()
+                }
+                .builders()
+            })
+            .collect();
+        let mut archipelago = create_archipelago(builders, self.migration)?;
+        for (island, saved) in archipelago.islands.iter_mut().zip(saved) {
+            island.load(saved);
+            if let Some(encoding) = self.encoding {
+                island.set_encoding(encoding)?;
+            }
+            if let Some(symmetry) = self.symmetry {
+                island.set_symmetry(symmetry);
+            }
+        }
+        Ok(archipelago)
+    }
+}
+
+/// --init is a single initializer or a mix like `template:60,random:40`
+/// noisy replaces a share of the template genes given by --noise, snapshot copies the genomes of --init-from, automata as the grids they grow
+fn initializer(options: &Options) -> Result<Initializer, CliError> {
+    let noise: f32 = options.value("noise", "a chance between 0 and 1")?.unwrap_or(0.1);
+    let snapshot = options.text("init-from").map(PathBuf::from);
+    let spec = options.text("init").unwrap_or("template");
+    let invalid = |value: &str| CliError::InvalidValue {
+        flag: "init".to_string(),
+        value: value.to_string(),
+        expected: "template, noisy, random, stratified, snapshot or a mix like template:60,random:40",
+    };
+    let mut parts = Vec::new();
+    for part in spec.split(',') {
+        let (name, share) = match part.split_once(':') {
+            Some((name, share)) => (name.trim(), share.trim().parse::<f32>().map_err(|_| invalid(part))?),
+            None => (part.trim(), 100.0),
+        };
+        let initializer = match name {
+            "template" => Initializer::Template,
+            "noisy" => Initializer::Noisy(noise),
+            "random" => Initializer::Random,
+            "stratified" => Initializer::Stratified,
+            "snapshot" => {
+                let path = snapshot.clone().ok_or(CliError::MissingValue("init-from".to_string()))?;
+                let saved = snapshot::read(&path).map_err(|error| CliError::Read { path, error })?;
+                // automaton genomes are rules, the grids they grow into are what the new genomes copy
+                let grids = saved.iter().flat_map(|island| {
+                    island.genomes.iter().map(|genome| island.encoding.develop(genome).unwrap_or_else(|| genome.clone()))
+                });
+                Initializer::Genomes(grids.collect())
+            }
+            _ => return Err(invalid(part)),
+        };
+        parts.push((initializer, share));
+    }
+    Ok(match parts.len() {
+        1 => parts.remove(0).0,
+        _ => Initializer::Mix(parts),
+    })
+}
+
+/// What the viewer was started with, nannou builds the model without arguments
+struct Viewer {
+    world: World,
+    generations: Option<usize>,
+    out: PathBuf,
+}
+
+static VIEWER: OnceLock<Viewer> = OnceLock::new();
+
+/// Evolves without a window, from a fresh archipelago or from a snapshot, see cli::usage
+/// The last generation is written to the snapshot file, out/snapshot.txt by default
+fn headless(options: &Options, saved: Option<Vec<SavedIsland>>) -> Result<(), CliError> {
+    let number = "a number";
+    let whole = "a whole number";
+    let generations = options.generations()?.unwrap_or(1000);
+    let out = options.out().unwrap_or_else(|| "export".into());
+    let mut exporter = Exporter::new(&out);
+    exporter.every = options.value("export-every", whole)?.unwrap_or(0);
+    exporter.extension = options
+        .choice("format", ["png", "ppm"], |f| f, "png or ppm")?
+        .unwrap_or("png")
+        .to_string();
+
+    let mut config = RecordingConfig::default();
+    let record = options.text("record").map(PathBuf::from);
+    if let Some(directory) = &record {
+        config.directory = directory.clone();
+    }
+    config.stride = options.value("record-stride", whole)?.unwrap_or(config.stride);
+    config.zoom = options.value("zoom", number)?.unwrap_or(config.zoom);
+    if let Some((width, height)) = options.dimensions("resolution")? {
+        config.width = width as u32;
+        config.height = height as u32;
+    }
+    let mut recorder = Recorder::new(config);
+    recorder.recording = record.is_some();
+    recorder.software = true;
+
+    let mut termination = Termination::default();
+    termination.generations = Some(generations).filter(|&n| n > 0);
+    termination.target_cost = options.value("target-cost", number)?;
+    termination.budget = options.value("time-budget", "seconds")?.map(Duration::from_secs_f32);
+    termination.stagnation = options.value("stagnation", whole)?;
+    termination.collapse = options.value("collapse", "a similarity between 0 and 1")?;
+    // without any criterion the run would never end and never write its snapshot
+    let unbounded = termination.target_cost.is_none()
+        && termination.budget.is_none()
+        && termination.stagnation.is_none()
+        && termination.collapse.is_none();
+    if termination.generations.is_none() && unbounded {
+        return Err(CliError::InvalidValue {
+            flag: "generations".to_string(),
+            value: generations.to_string(),
+            expected: "more than 0, --generations 0 needs --target-cost, --time-budget, --stagnation or --collapse",
+        });
+    }
+
+    let world = World::from_options(options)?;
+    let islands = world.islands;
+    let mut archipelago = match saved {
+        Some(saved) => world.load(saved)?,
+        None => world.create()?,
+    };
+    let resize = options.value("resize", "a chance between 0 and 1")?;
+    let max_side: Option<usize> = options.value("max-side", whole)?;
+    let transform = options.value("transform", "a chance between 0 and 1")?;
+    let crossover = options.choice("crossover", Crossover::ALL, Crossover::name, "uniform or regions")?;
+    for island in archipelago.islands.iter_mut() {
+        let side = island.population.iter().map(|b| b.genome.dimensions()).map(|(w, h)| w.max(h)).max();
+        if let (Some(max_side), Some(side)) = (max_side, side) {
+            if max_side < side {
+                return Err(CliError::InvalidValue {
+                    flag: "max-side".to_string(),
+                    value: max_side.to_string(),
+                    expected: "at least the longest side of the genomes",
+                });
+            }
+        }
+        let settings = &mut island.settings;
+        settings.resize = resize.unwrap_or(settings.resize);
+        settings.max_side = max_side.unwrap_or(settings.max_side);
+        settings.transform = transform.unwrap_or(settings.transform);
+        settings.crossover = crossover.unwrap_or(settings.crossover);
+    }
+    let metrics = match options.text("metrics").map(PathBuf::from) {
+        Some(path) => Some(MetricsSink::create(&path).map_err(|error| CliError::Write { path, error })?),
+        None => None,
+    };
+    let mut headless = Headless {
+        termination,
+        exporter,
+        recorder,
+        metrics,
+    };
+    // a failed run still saves what it reached, the failure is reported afterwards
+    let run = headless.run(&mut archipelago);
+    if let Ok(outcome) = &run {
+        info!("{}", outcome);
+    }
+    let path = options.text("snapshot").map_or_else(|| out.join("snapshot.txt"), PathBuf::from);
+    snapshot::write(&path, &archipelago).map_err(|error| CliError::Write { path: path.clone(), error })?;
+    info!("wrote snapshot to {}", path.display());
+    for island in archipelago.islands.iter_mut() {
+        // a single island keeps the exact path, several islands get numbered files
+        let number = island.island;
+        let path_of = |path: &str| match islands {
+            1 => PathBuf::from(path),
+            _ => island_path(path, number),
+        };
+        if let Some(path) = options.text("genealogy").map(path_of) {
+            island.export_genealogy(&path).map_err(|error| CliError::Write { path: path.clone(), error })?;
+            info!("wrote genealogy to {}", path.display());
+        }
+        if let Some(path) = options.text("provenance").map(path_of) {
+            island.export_provenance(&path).map_err(|error| CliError::Write { path: path.clone(), error })?;
+            info!("wrote provenance to {}", path.display());
+        }
+    }
+    run.map(|_| ()).map_err(|e| CliError::Run(e.to_string()))
+}
+
+/// Writes every genome and a contact sheet per island, from a snapshot
+/// or from a fresh archipelago which evolved for --generations
+fn export_images(options: &Options, snapshot: Option<PathBuf>) -> Result<(), CliError> {
+    let world = World::from_options(options)?;
+    let mut exporter = Exporter::new(options.out().unwrap_or_else(|| "export".into()));
+    exporter.extension = options
+        .choice("format", ["png", "ppm"], |f| f, "png or ppm")?
+        .unwrap_or("png")
+        .to_string();
+    exporter.scale = options.value("scale", "a whole number")?.unwrap_or(exporter.scale).max(1);
+    exporter.columns = options.value("columns", "a whole number")?.unwrap_or(exporter.columns).max(1);
+    let generations = options.generations()?.unwrap_or(0);
+    let mut archipelago = match snapshot {
+        Some(path) => world.load(snapshot::read(&path).map_err(|error| CliError::Read { path, error })?)?,
+        None => world.create()?,
+    };
+    for _ in 0..generations {
+        archipelago.evolve()?;
+    }
+    for island in archipelago.islands.iter() {
+        let exported = exporter.export_genomes(island).and_then(|()| exporter.export_sheet(island));
+        let sheet = exported.map_err(|e| CliError::Write {
+            path: exporter.directory.clone(),
+            error: std::io::Error::new(std::io::ErrorKind::Other, e),
+        })?;
+        info!("exported {} genomes and {}", island.population.len(), sheet.display());
+    }
+    Ok(())
+}
+
+/// Times --generations generations and reports how many run per second
+fn bench(options: &Options) -> Result<(), CliError> {
+    let world = World::from_options(options)?;
+    let generations = options.generations()?.unwrap_or(200).max(1);
+    let mut archipelago = world.create()?;
+    let mut durations = Vec::with_capacity(generations);
+    let start = Instant::now();
+    for _ in 0..generations {
+        let generation = Instant::now();
+        archipelago.evolve()?;
+        archipelago.step();
+        durations.push(generation.elapsed());
+    }
+    let total = start.elapsed();
+    let (min, max) = (durations.iter().min().unwrap(), durations.iter().max().unwrap());
+    println!(
+        "{} generations of {} island(s) with {} blobs and {}x{} genomes in {:.2}s",
+        generations,
+        world.islands,
+        archipelago.islands[0].population.len(),
+        world.genome.0,
+        world.genome.1,
+        total.as_secs_f32(),
+    );
+    println!(
+        "{:.1} generations per second, a generation took {:.2}ms on average, between {:.2}ms and {:.2}ms",
+        generations as f32 / total.as_secs_f32(),
+        total.as_secs_f32() * 1000.0 / generations as f32,
+        min.as_secs_f32() * 1000.0,
+        max.as_secs_f32() * 1000.0,
+    );
+    Ok(())
+}
+
+/// tree.dot -> tree_island_2.dot
+fn island_path(path: &str, island: usize) -> std::path::PathBuf {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("island");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_island_{}.{}", stem, island, extension),
+        None => format!("{}_island_{}", stem, island),
+    };
+    path.with_file_name(name)
+}
+
+/// Places the islands next to each other from left to right, each one around its own offset
+fn create_archipelago(
+    islands: Vec<(PopulationBuilder, ControllerBuilder)>,
+    migration: Migration,
+) -> Result<Archipelago, EvolutionError> {
+    let create = |(population, controller): &(PopulationBuilder, ControllerBuilder), offset: Vec2| {
+        controller.clone().population(population.clone().offset(offset)).build()
+    };
+    // the widest island decides how far apart they are
+    let mut spacing: f32 = 0.0;
+    for island in islands.iter() {
+        spacing = spacing.max(population_bounds(&create(island, Vec2::ZERO)?.population).map_or(0.0, |b| b.w()) * 1.5);
+    }
+    let center = (islands.len() - 1) as f32 / 2.0;
+    let controllers = islands
+        .iter()
+        .enumerate()
+        .map(|(i, island)| create(island, Vec2::new((i as f32 - center) * spacing, 0.0)))
+        .collect::<Result<_, _>>()?;
+    Ok(Archipelago::new(controllers, migration))
 }
 
 fn model(app: &App) -> Model {
-    let genome_length = 12 * 12;
-    let blob_size = 3.0;
-    let blob_amount: u16 = 32;
-    let points = distribute_uniformly(blob_amount, (genome_length as f32).sqrt() * blob_size);
-    let params = Some((points, blob_size, blob_amount, genome_length));
-    let population = SimpleBlobController::create_like(params);
+    let viewer = VIEWER.get_or_init(|| Viewer {
+        world: World {
+            islands: 1,
+            migration: Migration::default(),
+            genome: GENOME,
+            blobs: 32,
+            template: Template::Runner,
+            initializer: Initializer::Template,
+            placement: Placement::Rings,
+            spacing: 1.0,
+            symmetry: None,
+            encoding: None,
+            preservation: None,
+        },
+        generations: None,
+        out: "export".into(),
+    });
+    let archipelago = match viewer.world.create() {
+        Ok(archipelago) => archipelago,
+        Err(e) => {
+            error!("failed to create the islands: {}", e);
+            std::process::exit(2);
+        }
+    };
 
     let window_id = app
         .new_window()
         .size(WIDTH as u32, HEIGHT as u32)
         .view(view)
         .mouse_wheel(mouse_wheel)
+        .mouse_pressed(mouse_pressed)
+        .mouse_released(mouse_released)
+        .mouse_moved(mouse_moved)
+        .key_pressed(key_pressed)
         .raw_event(raw_window_event)
         .build()
         .unwrap();
     let window = app.window(window_id).unwrap();
     let egui = Egui::from_window(&window);
     let count = 128;
-    let position = Point2::new(0., 0.);
+    let mut termination = Termination::default();
+    termination.generations = viewer.generations;
 
     Model {
-        center: position,
-        zoom: 1.0,
-        controller: population,
+        camera: Camera::new(),
+        draw_mode: DrawMode::Rect,
+        overlay: Overlay::None,
+        atlases: Vec::new(),
+        archipelago,
         egui,
         count,
         window_id,
+        target_path: String::new(),
+        distance: Distance::Channel,
+        target_status: String::new(),
+        exporter: Exporter::new(&viewer.out),
+        export_status: String::new(),
+        recorder: Recorder::new(RecordingConfig {
+            width: WIDTH as u32,
+            height: HEIGHT as u32,
+            ..Default::default()
+        }),
+        scheduler: Scheduler::default(),
+        ticks: Ticks::default(),
+        metrics_path: "metrics.csv".to_string(),
+        metrics: None,
+        termination,
+        outcome: None,
+        diversity: None,
     }
 }
 
 fn update(app: &App, model: &mut Model, update: Update) {
     let Model {
         ref mut egui,
-        controller: ref mut population,
+        ref mut archipelago,
         ..
     } = *model;
 
     //SimpleBlobPopulation::evolve(blobs.population.iter_mut().map(|b| b.genome).collect());
-    population.update();
-    println!("FPS: {}", app.fps());
+    let exporter = &model.exporter;
+    let export_status = &mut model.export_status;
+    let metrics = &mut model.metrics;
+    let termination = &mut model.termination;
+    let outcome = &mut model.outcome;
+    model.ticks = model.scheduler.advance(archipelago, update.since_last, |archipelago| {
+        for controller in archipelago.islands.iter() {
+            match exporter.on_generation(controller) {
+                Ok(Some(path)) => *export_status = format!("exported {}", path.display()),
+                Ok(None) => {}
+                Err(e) => *export_status = format!("export failed: {}", e),
+            }
+            if let Some(sink) = metrics {
+                if let Err(e) = sink.record(&controller.metrics()) {
+                    error!("failed to write metrics: {}", e);
+                    *metrics = None;
+                }
+            }
+        }
+        *outcome = termination.check(archipelago);
+        if let Some(outcome) = outcome {
+            info!("{}", outcome);
+        }
+        outcome.is_none()
+    });
+    if app.elapsed_frames() % 60 == 0 {
+        debug!("FPS: {:.1}, generation {}", app.fps(), archipelago.generation());
+    }
+    let recorded = if model.recorder.software {
+        model.recorder.config.center = model.camera.center;
+        model.recorder.config.zoom = model.camera.zoom;
+        model.recorder.rasterise(archipelago)
+    } else {
+        model.recorder.capture(&app.window(model.window_id).unwrap())
+    };
+    if let Err(e) = recorded {
+        model.export_status = format!("recording failed: {}", e);
+        model.recorder.recording = false;
+    }
     //if app.time.round() as i32 % 5 == 0 {
     //}
 
-    let pos_shift = scroll(app, model.window_id, app.mouse.position());
-    model.center += pos_shift;
+    model.camera.update(&archipelago.selected().population);
+    match model.draw_mode {
+        DrawMode::Texture => {
+            model.atlases.truncate(archipelago.islands.len());
+            for (i, island) in archipelago.islands.iter().enumerate() {
+                match model.atlases.get_mut(i) {
+                    Some(atlas) => atlas.update(app, &island.population),
+                    None => model.atlases.push(GenomeAtlas::new(app, &island.population)),
+                }
+            }
+        }
+        _ => model.atlases.clear(),
+    }
+    let selected = archipelago.selected();
+    let key = (selected.island, selected.generation);
+    if model.diversity.as_ref().map_or(true, |(measured, _)| *measured != key) {
+        model.diversity = Some((key, Diversity::measure(&selected.population)));
+    }
     egui.set_elapsed_time(update.since_start);
     let ctx = egui.begin_frame();
 
+    let mut restart = false;
     egui::Window::new("Workshop window").show(&ctx, |ui| {
         ui.add(egui::Slider::new(&mut model.count, 0..=2000).text("circle count"))
             .changed();
+        ui.horizontal(|ui| {
+            let islands = archipelago.islands.len();
+            egui::ComboBox::from_label(format!("island of {}", islands))
+                .selected_text(format!("island {}", archipelago.selected))
+                .show_ui(ui, |ui| {
+                    for i in 0..islands {
+                        if ui.selectable_value(&mut archipelago.selected, i, format!("island {}", i)).clicked() {
+                            model.camera.follow = None;
+                        }
+                    }
+                });
+            if ui.button("add island").clicked() {
+                // the new island keeps the distance between islands, to the right of the last one
+                let last = archipelago.islands.last().and_then(|island| population_bounds(&island.population));
+                let offset = last.map_or(Vec2::ZERO, |b| Vec2::new(b.x() + b.w() * 1.5, b.y()));
+                let island = VIEWER.get().map(|viewer| viewer.world.island(offset));
+                match island {
+                    Some(Ok(island)) => archipelago.add(island),
+                    Some(Err(e)) => error!("failed to add an island: {}", e),
+                    None => {}
+                }
+            }
+        });
+        let migration = &mut archipelago.migration;
+        ui.add(egui::Slider::new(&mut migration.every, 0..=200).text("migrate every n generations"));
+        ui.add(egui::Slider::new(&mut migration.amount, 0..=8).text("migrants"));
+        egui::ComboBox::from_label("topology")
+            .selected_text(migration.topology.name())
+            .show_ui(ui, |ui| {
+                for topology in Topology::ALL {
+                    ui.selectable_value(&mut migration.topology, topology, topology.name());
+                }
+            });
+        ui.separator();
+        let mut selection = archipelago.selected().selection;
+        egui::ComboBox::from_label("selection")
+            .selected_text(selection.name())
+            .show_ui(ui, |ui| {
+                for option in Selection::ALL {
+                    ui.selectable_value(&mut selection, option, option.name());
+                }
+            });
+        for island in archipelago.islands.iter_mut() {
+            island.selection = selection;
+        }
+        model.scheduler.manual = selection == Selection::Interactive;
+        ui.horizontal(|ui| {
+            if selection == Selection::Interactive && ui.button("next generation (enter)").clicked() {
+                model.scheduler.request();
+            }
+            if ui.button("undo generation (u)").clicked() {
+                undo(archipelago);
+            }
+            if selection == Selection::Interactive && ui.button("clear ratings (c)").clicked() {
+                archipelago.selected_mut().ratings.fill(0);
+            }
+        });
+        if selection == Selection::Interactive {
+            ui.label(format!("click a blob to rate it, up to {} points", MAX_RATING));
+        }
+        if let Some(range) = archipelago.history() {
+            // scrubbing pauses the run, continuing branches off the shown generation
+            let mut generation = archipelago.generation();
+            let slider = egui::Slider::new(&mut generation, range).text("history");
+            if ui.add(slider).changed() && archipelago.restore(generation) {
+                model.scheduler.paused = true;
+            }
+            let (stored, full) = archipelago.selected().history.stored_genes();
+            ui.label(format!("{} of {} genes stored, running on forgets later generations", stored, full));
+        }
+
+        let bounds = archipelago.bounds();
+        let population = archipelago.selected_mut();
+        egui::ComboBox::from_label("draw mode")
+            .selected_text(model.draw_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in DrawMode::ALL {
+                    ui.selectable_value(&mut model.draw_mode, mode, mode.name());
+                }
+            });
+        egui::ComboBox::from_label("overlay")
+            .selected_text(model.overlay.name())
+            .show_ui(ui, |ui| {
+                for overlay in Overlay::ALL {
+                    ui.selectable_value(&mut model.overlay, overlay, overlay.name());
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label(format!("churn over {} births", population.provenance.births()));
+            if ui.button("reset").clicked() {
+                population.provenance.reset();
+            }
+            if ui.button("export provenance").clicked() {
+                let name = format!("provenance_{:06}_island_{}.csv", population.generation, population.island);
+                let path = model.exporter.directory.join(name);
+                model.export_status = match population.export_provenance(&path) {
+                    Ok(()) => format!("exported {}", path.display()),
+                    Err(e) => format!("export failed: {}", e),
+                };
+            }
+        });
+
+        ui.separator();
+        let scheduler = &mut model.scheduler;
+        ui.checkbox(&mut scheduler.paused, "paused");
+        ui.add(egui::Slider::new(&mut scheduler.generations_per_second, 0.0..=240.0).text("generations per second"));
+        ui.add(egui::Slider::new(&mut scheduler.fast_forward, 1..=64).text("fast forward"));
+        let mut budget = scheduler.budget.as_millis() as u64;
+        if ui.add(egui::Slider::new(&mut budget, 1..=100).text("frame budget (ms)")).changed() {
+            scheduler.budget = std::time::Duration::from_millis(budget);
+        }
+        ui.label(format!(
+            "generation {}: {} generations, {} physics ticks last frame{}",
+            population.generation,
+            model.ticks.generations,
+            model.ticks.physics,
+            if model.ticks.throttled { " (throttled)" } else { "" },
+        ));
+
+        ui.separator();
+        let mut symmetry = population.symmetry();
+        egui::ComboBox::from_label("symmetry")
+            .selected_text(symmetry.name())
+            .show_ui(ui, |ui| {
+                for option in Symmetry::ALL {
+                    ui.selectable_value(&mut symmetry, option, option.name());
+                }
+            });
+        if symmetry != population.symmetry() {
+            population.set_symmetry(symmetry);
+        }
+        let (width, height) = population.population[0].phenotype().dimensions();
+        let mut encoding = population.encoding;
+        egui::ComboBox::from_label("encoding")
+            .selected_text(encoding.name())
+            .show_ui(ui, |ui| {
+                for option in [Encoding::Direct, Encoding::automaton(width, height)] {
+                    let selected = encoding.name() == option.name();
+                    if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                        encoding = option;
+                    }
+                }
+            });
+        if let Encoding::Automaton { steps, .. } = &mut encoding {
+            ui.add(egui::Slider::new(steps, 0..=32).text("growth steps"));
+        }
+        if encoding != population.encoding {
+            if let Err(e) = population.set_encoding(encoding) {
+                error!("failed to change the encoding: {}", e);
+            }
+            // the genomes changed without a new generation
+            model.diversity = None;
+        }
+        let settings = &mut population.settings;
+        egui::ComboBox::from_label("crossover")
+            .selected_text(settings.crossover.name())
+            .show_ui(ui, |ui| {
+                for crossover in Crossover::ALL {
+                    ui.selectable_value(&mut settings.crossover, crossover, crossover.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut settings.transform, 0.0..=1.0).text("transform chance"));
+        ui.add(egui::Slider::new(&mut settings.resize, 0.0..=1.0).text("resize chance"));
+        ui.add(egui::Slider::new(&mut settings.min_side, 1..=64).text("min side"));
+        ui.add(egui::Slider::new(&mut settings.max_side, 1..=64).text("max side"));
+        if let Some(index) = model.camera.follow {
+            let blob = &population.population[index];
+            let (width, height) = blob.phenotype().dimensions();
+            ui.label(format!("blob {} is {}x{} cells grown from {} genes", index, width, height, blob.genome.len()));
+        }
+
+        ui.separator();
+        if let Some((_, diversity)) = &model.diversity {
+            ui.label(format!(
+                "similarity {:.3}, entropy {:.3}, {} clusters",
+                diversity.similarity,
+                diversity.mean_entropy(),
+                diversity.clusters
+            ));
+        }
+        let settings = &mut population.settings;
+        egui::ComboBox::from_label("diversity preservation")
+            .selected_text(settings.preservation.name())
+            .show_ui(ui, |ui| {
+                for preservation in Preservation::all() {
+                    let selected = settings.preservation.name() == preservation.name();
+                    if ui.selectable_label(selected, preservation.name()).clicked() && !selected {
+                        settings.preservation = preservation;
+                    }
+                }
+            });
+        match &mut settings.preservation {
+            Preservation::None => {}
+            Preservation::Sharing { radius } => {
+                ui.add(egui::Slider::new(radius, 0.01..=0.5).text("sharing radius"));
+            }
+            Preservation::Crowding { factor } => {
+                ui.add(egui::Slider::new(factor, 1..=16).text("crowding factor"));
+            }
+            Preservation::Niching { radius, capacity } => {
+                ui.add(egui::Slider::new(radius, 0.01..=0.5).text("niche radius"));
+                ui.add(egui::Slider::new(capacity, 1..=8).text("niche capacity"));
+            }
+            Preservation::Speciation { threshold, stagnation } => {
+                ui.add(egui::Slider::new(threshold, 0.01..=0.5).text("compatibility threshold"));
+                ui.add(egui::Slider::new(stagnation, 1..=100).text("stagnation limit"));
+            }
+        }
+        if !population.species.species.is_empty() {
+            ui.label(format!("{} species", population.species.species.len()));
+            egui::ScrollArea::vertical().max_height(120.0).show(ui, |ui| {
+                for species in population.species.species.iter() {
+                    let color: Rgb = hsl(species.hue(), 0.8, 0.6).into();
+                    let (r, g, b) = color.into_components();
+                    let color = egui::Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8);
+                    ui.colored_label(
+                        color,
+                        format!(
+                            "species {}: {} members, age {}, best {:.4}, {} children{}",
+                            species.id,
+                            species.members.len(),
+                            species.age,
+                            species.best,
+                            species.offspring,
+                            if species.stagnant > 0 { format!(", stagnant for {}", species.stagnant) } else { String::new() },
+                        ),
+                    );
+                }
+            });
+        }
+
+        ui.separator();
+        ui.label("target image (PNG/PPM)");
+        ui.text_edit_singleline(&mut model.target_path);
+        egui::ComboBox::from_label("distance")
+            .selected_text(model.distance.name())
+            .show_ui(ui, |ui| {
+                for distance in Distance::ALL {
+                    ui.selectable_value(&mut model.distance, distance, distance.name());
+                }
+            });
+        if ui.button("load target").clicked() {
+            model.target_status = match population.set_target(&model.target_path, model.distance) {
+                Ok(()) => format!("evolving towards {}", model.target_path),
+                Err(e) => format!("failed to load target: {}", e),
+            };
+        }
+        ui.label(&model.target_status);
+
+        ui.separator();
+        ui.add(egui::Slider::new(&mut model.exporter.every, 0..=500).text("export every n generations"));
+        ui.horizontal(|ui| {
+            let generation = population.generation;
+            if ui.button("export contact sheet").clicked() {
+                model.export_status = match model.exporter.export_sheet(population) {
+                    Ok(path) => format!("exported {}", path.display()),
+                    Err(e) => format!("export failed: {}", e),
+                };
+            }
+            if ui.button("export genomes").clicked() {
+                model.export_status = match model.exporter.export_genomes(population) {
+                    Ok(()) => format!("exported generation {}", generation),
+                    Err(e) => format!("export failed: {}", e),
+                };
+            }
+        });
+        ui.label(&model.export_status);
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut model.recorder.recording, "record");
+            ui.checkbox(&mut model.recorder.software, "software rasteriser");
+        });
+        ui.add(egui::Slider::new(&mut model.recorder.config.stride, 1..=60).text("frame stride"));
+        ui.label(format!("{} frames recorded", model.recorder.frames()));
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut model.metrics_path);
+            match model.metrics {
+                Some(_) => {
+                    if ui.button("stop metrics").clicked() {
+                        if let Some(Err(e)) = model.metrics.take().map(|mut sink| sink.flush()) {
+                            error!("failed to write metrics: {}", e);
+                        }
+                    }
+                }
+                None => {
+                    if ui.button("write metrics").clicked() {
+                        match MetricsSink::create(&model.metrics_path) {
+                            Ok(sink) => model.metrics = Some(sink),
+                            Err(e) => error!("failed to create {}: {}", model.metrics_path, e),
+                        }
+                    }
+                }
+            }
+        });
+
+        ui.separator();
+        match population.common_ancestor {
+            Some(id) => ui.label(format!("most recent common ancestor: {}", id)),
+            None => ui.label("no common ancestor"),
+        };
+        ui.horizontal(|ui| {
+            for extension in ["dot", "json"] {
+                if ui.button(format!("export family tree ({})", extension)).clicked() {
+                    let name = format!("genealogy_{:06}_island_{}.{}", population.generation, population.island, extension);
+                    let path = model.exporter.directory.join(name);
+                    model.export_status = match population.export_genealogy(&path) {
+                        Ok(()) => format!("exported {}", path.display()),
+                        Err(e) => format!("export failed: {}", e),
+                    };
+                }
+            }
+        });
+
+        ui.separator();
+        let termination = &mut model.termination;
+        criterion(ui, &mut termination.generations, 1000, 1..=100_000, "generation limit");
+        criterion(ui, &mut termination.target_cost, -2.7, -3.0..=3.0, "target cost");
+        let mut seconds = termination.budget.map(|b| b.as_secs_f32());
+        criterion(ui, &mut seconds, 60.0, 1.0..=3600.0, "time budget (s)");
+        termination.budget = seconds.map(Duration::from_secs_f32);
+        criterion(ui, &mut termination.stagnation, 200, 1..=5000, "stagnation limit");
+        criterion(ui, &mut termination.collapse, 0.99, 0.5..=1.0, "collapse similarity");
+        ui.horizontal(|ui| {
+            restart = ui.button("restart run").clicked();
+            ui.label(format!("running for {:.0}s", termination.elapsed().as_secs_f32()));
+        });
+        if let Some(outcome) = &model.outcome {
+            ui.label(outcome.to_string());
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let viewport = app.window(model.window_id).unwrap().rect();
+            if ui.button("fit island").clicked() {
+                model.camera.fit(population_bounds(&population.population), viewport);
+            }
+            if ui.button("fit all").clicked() {
+                model.camera.fit(bounds, viewport);
+            }
+            match model.camera.follow {
+                Some(index) => {
+                    if ui.button(format!("stop following blob {}", index)).clicked() {
+                        model.camera.follow = None;
+                    }
+                }
+                None => {
+                    ui.label("click a blob to follow it");
+                }
+            }
+        });
+    });
+    if restart {
+        model.termination.restart(archipelago);
+        model.outcome = None;
+        model.scheduler.paused = false;
+    }
+}
+
+/// A checkbox which enables a termination criterion and a slider for its value
+fn criterion<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    value: &mut Option<T>,
+    default: T,
+    range: RangeInclusive<T>,
+    text: &str,
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = value.is_some();
+        if ui.checkbox(&mut enabled, text).changed() {
+            *value = enabled.then(|| default);
+        }
+        if let Some(value) = value {
+            ui.add(egui::Slider::new(value, range));
+        }
     });
 }
 
@@ -102,7 +1062,17 @@ fn view(app: &App, model: &Model, frame: Frame) {
     //model.blob.draw_as_polyline(&draw, (0., 0.), 30.);
     //draw_function(&draw, &win, |x| (1./win.h()) * x * x, 1.);
 
-    model.controller.draw(&draw, model);
+    model.archipelago.draw(&draw, &model.view());
+
+    if let Some(index) = model.camera.follow {
+        let bounds = blob_bounds(&model.archipelago.selected().population, index);
+        draw.rect()
+            .xy(model.transform(bounds.xy()))
+            .wh(bounds.wh() * model.camera.zoom)
+            .no_fill()
+            .stroke(YELLOW)
+            .stroke_weight(2.0);
+    }
 
     draw.to_frame(app, &frame).unwrap();
 
@@ -116,28 +1086,60 @@ fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event:
     model.egui.handle_raw_event(event);
 }
 
-fn mouse_wheel(_app: &App, model: &mut Model, dt: MouseScrollDelta, _phase: TouchPhase) {
-    let zoom_change = zoom(dt, model.zoom);
-    model.zoom = (model.zoom + zoom_change).clamp(0.25, 4.0);
+fn mouse_wheel(app: &App, model: &mut Model, dt: MouseScrollDelta, _phase: TouchPhase) {
+    if model.egui.ctx().wants_pointer_input() {
+        return;
+    }
+    model.camera.scroll(dt, app.mouse.position());
 }
 
-fn scroll(app: &App, window_id: WindowId, pos: Point2) -> Vec2 {
-    let window = app.window(window_id).unwrap();
-    let pad = 50.0;
-    let step_size = 10.0;
-    let rect = window.rect().pad(pad);
-    let mut vec = Vec2::new(0.0, 0.0);
-    if pos.x > rect.right() || pos.x < rect.left() || pos.y > rect.top() || pos.y < rect.bottom() {
-        vec = Vec2::new(pos.x, pos.y);
-        vec = vec.normalize();
-        vec *= step_size;
-    }
-    vec
-}
-
-fn zoom(dt: MouseScrollDelta, current: f32) -> f32 {
-    match dt {
-        MouseScrollDelta::LineDelta(_, y) => 0.03 * current * y,
-        MouseScrollDelta::PixelDelta(_) => 0.0,
+fn mouse_pressed(app: &App, model: &mut Model, button: MouseButton) {
+    if button == MouseButton::Left && !model.egui.ctx().wants_pointer_input() {
+        model.camera.start_drag(app.mouse.position());
+    }
+}
+
+fn mouse_moved(_app: &App, model: &mut Model, position: Point2) {
+    model.camera.drag_to(position);
+}
+
+fn mouse_released(app: &App, model: &mut Model, button: MouseButton) {
+    if button == MouseButton::Left && model.camera.end_drag() {
+        let at = model.camera.to_world(app.mouse.position());
+        let hit = model.archipelago.blob_at(at);
+        if let Some((island, _)) = hit {
+            model.archipelago.selected = island;
+        }
+        // while rating, clicks rate blobs instead of following them
+        match (model.archipelago.selected().selection, hit) {
+            (Selection::Interactive, Some((_, blob))) => model.archipelago.selected_mut().rate(blob),
+            _ => model.camera.follow = hit.map(|(_, blob)| blob),
+        }
+    }
+}
+
+/// Goes back one generation on every island
+fn undo(archipelago: &mut Archipelago) {
+    if !archipelago.undo() {
+        info!("there is no generation to undo");
+    }
+}
+
+fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if model.egui.ctx().wants_keyboard_input() {
+        return;
+    }
+    match key {
+        Key::F => {
+            let viewport = app.window(model.window_id).unwrap().rect();
+            model.camera.fit(model.archipelago.bounds(), viewport);
+        }
+        Key::Escape => model.camera.follow = None,
+        Key::Return | Key::Space if model.scheduler.manual => model.scheduler.request(),
+        Key::U => undo(&mut model.archipelago),
+        Key::C if model.scheduler.manual => model.archipelago.selected_mut().ratings.fill(0),
+        _ => {
+            model.camera.key(key);
+        }
     }
 }
@@ -0,0 +1,127 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use nannou::image;
+use nannou::prelude::*;
+
+use crate::{
+    cli::{CliError, Options},
+    error, info,
+};
+
+/// The frames of a recording and how fast they are played back
+struct Recording {
+    frames: Vec<PathBuf>,
+    fps: f32,
+}
+
+static RECORDING: OnceLock<Recording> = OnceLock::new();
+
+/// The playback speeds --fps accepts, anything else either never advances or never stops advancing
+const FPS: RangeInclusive<f32> = 0.1..=1000.0;
+
+struct Model {
+    frame: usize,
+    /// the texture of the frame, reloaded whenever the frame changes
+    texture: Option<(usize, wgpu::Texture)>,
+    playing: bool,
+    since_frame: Duration,
+}
+
+/// Plays back the frames a Recorder wrote into a directory, in the order of their names
+/// Space pauses, left and right step through the frames, home starts over
+pub fn start(directory: PathBuf, options: &Options) -> Result<(), CliError> {
+    let fps: f32 = options.value("fps", "frames per second")?.unwrap_or(30.0);
+    if !FPS.contains(&fps) {
+        return Err(CliError::InvalidValue {
+            flag: "fps".to_string(),
+            value: options.text("fps").unwrap_or_default().to_string(),
+            expected: "frames per second between 0.1 and 1000",
+        });
+    }
+    let entries = fs::read_dir(&directory).map_err(|error| CliError::Read { path: directory.clone(), error })?;
+    let mut frames: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.file_name().and_then(|n| n.to_str()).map_or(false, |n| n.starts_with("frame_")))
+        .collect();
+    frames.sort();
+    if frames.is_empty() {
+        return Err(CliError::Read {
+            path: directory,
+            error: std::io::Error::new(std::io::ErrorKind::NotFound, "no frame_ files in the recording"),
+        });
+    }
+    info!("replaying {} frames at {} fps", frames.len(), fps);
+    RECORDING.get_or_init(|| Recording { frames, fps });
+    nannou::app(model).update(update).run();
+    Ok(())
+}
+
+fn recording() -> &'static Recording {
+    RECORDING.get().expect("the recording is read before the replay starts")
+}
+
+fn model(app: &App) -> Model {
+    app.new_window()
+        .size(640, 360)
+        .title("evolution replay")
+        .view(view)
+        .key_pressed(key_pressed)
+        .build()
+        .unwrap();
+    Model {
+        frame: 0,
+        texture: None,
+        playing: true,
+        since_frame: Duration::ZERO,
+    }
+}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    let recording = recording();
+    if model.playing {
+        model.since_frame += update.since_last;
+        let period = Duration::from_secs_f32(1.0 / recording.fps);
+        while model.since_frame >= period {
+            model.since_frame -= period;
+            model.frame = (model.frame + 1) % recording.frames.len();
+        }
+    }
+    if model.texture.as_ref().map(|(frame, _)| *frame) != Some(model.frame) {
+        let path = &recording.frames[model.frame];
+        match image::open(path) {
+            Ok(image) => model.texture = Some((model.frame, wgpu::Texture::from_image(app, &image))),
+            Err(e) => {
+                error!("failed to read {}: {}", path.display(), e);
+                model.playing = false;
+            }
+        }
+    }
+}
+
+fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+    let frames = recording().frames.len();
+    match key {
+        Key::Space => model.playing = !model.playing,
+        Key::Right => model.frame = (model.frame + 1) % frames,
+        Key::Left => model.frame = (model.frame + frames - 1) % frames,
+        Key::Home => model.frame = 0,
+        _ => {}
+    }
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    draw.background().rgb(0.11, 0.12, 0.13);
+    let window = app.window_rect();
+    if let Some((_, texture)) = &model.texture {
+        draw.texture(texture).wh(window.wh());
+    }
+    draw.text(&format!("frame {} of {}", model.frame + 1, recording().frames.len()))
+        .xy(window.top_left() + vec2(70.0, -12.0))
+        .color(WHITE);
+    draw.to_frame(app, &frame).unwrap();
+}
@@ -0,0 +1,100 @@
+use nannou::geom::{pt2, Rect};
+use nannou::image::DynamicImage;
+use nannou::{wgpu, App};
+
+use crate::{
+    evolution::population::SimpleBlobPopulation,
+    simulation::export::{contact_sheet, tile_dimensions},
+};
+
+/// All genomes of a population packed into a single texture, one texel per gene
+/// Drawing the atlas replaces one draw.rect() per gene by one quad per blob
+/// It is only painted again when the genomes changed, into the same texture while the size stays
+pub struct GenomeAtlas {
+    pub texture: wgpu::Texture,
+    columns: u32,
+    rows: u32,
+    /// the largest genome of the population, every tile has this size
+    tile: (u32, u32),
+    /// the fingerprint of the genomes the texture shows
+    painted: u64,
+}
+
+impl GenomeAtlas {
+    pub fn new(app: &App, population: &SimpleBlobPopulation) -> Self {
+        let (columns, rows) = Self::grid(population);
+        let image = contact_sheet(population, columns, 1, 0);
+        let texture = wgpu::Texture::from_image(app, &DynamicImage::ImageRgb8(image));
+        GenomeAtlas {
+            texture,
+            columns,
+            rows,
+            tile: tile_dimensions(population),
+            painted: fingerprint(population),
+        }
+    }
+
+    /// Paints the population again if its genomes changed since the last time
+    pub fn update(&mut self, app: &App, population: &SimpleBlobPopulation) {
+        let current = fingerprint(population);
+        if current == self.painted {
+            return;
+        }
+        let (columns, rows) = Self::grid(population);
+        let image = contact_sheet(population, columns, 1, 0);
+        if self.texture.size() != [image.width(), image.height()] {
+            *self = Self::new(app, population);
+            return;
+        }
+        let window = app.main_window();
+        let device = window.device();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("genome atlas"),
+        });
+        let rgba = DynamicImage::ImageRgb8(image).to_rgba8();
+        self.texture.upload_data(device, &mut encoder, rgba.as_raw());
+        window.queue().submit(Some(encoder.finish()));
+        self.columns = columns;
+        self.rows = rows;
+        self.tile = tile_dimensions(population);
+        self.painted = current;
+    }
+
+    fn grid(population: &SimpleBlobPopulation) -> (u32, u32) {
+        let columns = (population.len() as f32).sqrt().ceil().max(1.0) as u32;
+        let rows = (population.len() as u32 + columns - 1) / columns;
+        (columns, rows.max(1))
+    }
+
+    /// The area of the texture holding the genome at index with the given dimensions, in texture coordinates
+    /// (0, 0) is the top left corner of the atlas and (1, 1) the bottom right one
+    pub fn area(&self, index: usize, dimensions: (usize, usize)) -> Rect {
+        let index = index as u32;
+        let (column, row) = ((index % self.columns) as f32, (index / self.columns) as f32);
+        let (w, h) = (1.0 / self.columns as f32, 1.0 / self.rows as f32);
+        // genomes smaller than the tile only cover its top left corner
+        let used_w = w * dimensions.0 as f32 / self.tile.0.max(1) as f32;
+        let used_h = h * dimensions.1 as f32 / self.tile.1.max(1) as f32;
+        Rect::from_corners(pt2(column * w, row * h), pt2(column * w + used_w, row * h + used_h))
+    }
+}
+
+
+/// FNV-1a over the dimensions and colors of every phenotype, far cheaper than painting them
+fn fingerprint(population: &SimpleBlobPopulation) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |byte: u8| hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+    for blob in population.iter() {
+        let genome = blob.phenotype();
+        let (width, height) = genome.dimensions();
+        for byte in (width as u32).to_le_bytes().into_iter().chain((height as u32).to_le_bytes()) {
+            add(byte);
+        }
+        for gene in genome.iter() {
+            add(gene.r);
+            add(gene.g);
+            add(gene.b);
+        }
+    }
+    hash
+}
@@ -1,13 +1,33 @@
-use nannou::glam::Vec2;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use nannou::image::ImageError;
+use nannou::wgpu;
 
 use crate::{
     evolution::{
-        blob::RGB,
-        gene::{CostFunction, Creature, Evolve, Genome},
-        population::{compare_to, create_black, extract_velocity, move_to, SimpleBlobPopulation}, square::Square,
+        blob::{DrawMode, Overlay, RGB},
+        builder::{GenomeBuilder, PopulationBuilder, Template},
+        development::{Encoding, AUTOMATON_GENOME},
+        error::EvolutionError,
+        gene::{CostFunction, Evolve, EvolveSettings, Genome},
+        lineage::Genealogy,
+        population::{create_black, extract_velocity, match_reference, SimpleBlobPopulation},
+        species::Speciation,
+        square::{Quadrant, Square, Symmetry},
+        target::{load_target, Distance},
+    },
+    simulation::{
+        history::{History, Snapshot},
+        snapshot::SavedIsland,
     },
+    statistics::{metrics::GenerationMetrics, provenance::ProvenanceMap},
     util::Create,
-    Nannou,
+    error, Nannou, View,
 };
 
 pub trait Control<T>
@@ -16,52 +36,464 @@ where
 {
 }
 
+/// Where the selection signal of a generation comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    CostFunction,
+    /// a person rates the blobs, generations only happen when asked for
+    Interactive,
+}
+
+impl Selection {
+    pub const ALL: [Selection; 2] = [Selection::CostFunction, Selection::Interactive];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Selection::CostFunction => "cost function",
+            Selection::Interactive => "interactive",
+        }
+    }
+}
+
+/// The highest rating of a blob, ratings above it wrap around to 0
+pub const MAX_RATING: u8 = 3;
+
 pub struct SimpleBlobController {
     pub population: SimpleBlobPopulation,
+    /// rates the grids which are drawn, see genome_cost
     pub cost_function: CostFunction<Genome<RGB>>,
+    /// how the genomes grow into what is drawn, change it with set_encoding
+    pub encoding: Encoding,
+    pub settings: EvolveSettings,
+    pub generation: usize,
+    /// mutations and time spent during the last generation
+    pub mutations: usize,
+    pub duration: Duration,
+    pub genealogy: Genealogy,
+    /// refreshed whenever the genealogy is pruned, searching it every frame is too slow
+    pub common_ancestor: Option<u64>,
+    pub provenance: ProvenanceMap,
+    /// only filled while the settings use Preservation::Speciation
+    pub species: Speciation<RGB>,
+    /// the index of this population inside its Archipelago
+    pub island: usize,
+    pub selection: Selection,
+    /// one rating per blob while the selection is interactive, higher is better
+    pub ratings: Vec<u8>,
+    /// the past generations which can be restored
+    pub history: History,
 }
 
+/// The amount of generations which can be restored
+const HISTORY: usize = 512;
+
+/// Individuals without living descendants are forgotten this often
+const PRUNE_EVERY: usize = 64;
+
 impl Nannou for SimpleBlobController {
-    fn draw(&self, draw: &nannou::prelude::Draw, model: &crate::Model) {
-        for blob in &self.population {
-            blob.draw(&draw, &model);
+    fn draw(&self, draw: &nannou::prelude::Draw, view: &View) {
+        if let (DrawMode::Texture, Some(atlas)) = (view.draw_mode, view.atlases.get(self.island)) {
+            // sample the genes as hard edged squares instead of blurring them
+            let sampler = wgpu::SamplerBuilder::new()
+                .min_filter(wgpu::FilterMode::Nearest)
+                .mag_filter(wgpu::FilterMode::Nearest)
+                .into_descriptor();
+            let draw = draw.sampler(sampler);
+            for (i, blob) in self.population.iter().enumerate() {
+                let position = view.transform(blob.position());
+                let size = blob.size() * view.camera.zoom;
+                let area = atlas.area(i, blob.phenotype().dimensions());
+                blob.draw_texture(&draw, position, size, &atlas.texture, area);
+            }
+        } else {
+            for blob in &self.population {
+                blob.draw(&draw, view);
+            }
+        }
+
+        let churn = match view.overlay {
+            Overlay::Churn => self.provenance.churn(),
+            _ => BTreeMap::new(),
+        };
+        for (i, blob) in self.population.iter().enumerate() {
+            let position = view.transform(blob.position());
+            let size = blob.size() * view.camera.zoom;
+            match view.overlay {
+                Overlay::None => {}
+                Overlay::Provenance => blob.draw_provenance(draw, position, size),
+                Overlay::Churn => blob.draw_heat(draw, position, size, &churn),
+                Overlay::Species => {
+                    if let Some(species) = self.species.labels.get(i).map(|&s| &self.species.species[s]) {
+                        blob.draw_outline(draw, position, size, species.hue());
+                    }
+                }
+            }
+            if self.selection == Selection::Interactive {
+                blob.draw_rating(draw, position, size, self.ratings[i]);
+            }
         }
     }
 
     fn update(&mut self) {
-        SimpleBlobPopulation::evolve(&mut self.population, self.cost_function);
+        if let Err(e) = self.evolve() {
+            error!("island {} failed to evolve: {}", self.island, e);
+        }
+        self.step();
+    }
+}
 
-        for blob in self.population.iter_mut() {
-            let genome = blob.extract_genome();
-            let velocity = extract_velocity(genome);
-            blob.velocity = 10.0 * velocity;
-            blob.update();
+/// Builds a controller around a population
+/// The genomes are rated by how close they come to a red triangle on black
+#[derive(Debug, Clone)]
+pub struct ControllerBuilder {
+    population: PopulationBuilder,
+    encoding: Encoding,
+    settings: EvolveSettings,
+}
+
+impl Default for ControllerBuilder {
+    fn default() -> Self {
+        ControllerBuilder {
+            population: PopulationBuilder::default(),
+            encoding: Encoding::Direct,
+            settings: EvolveSettings::default(),
         }
     }
 }
 
-impl Create for SimpleBlobController {
-    type Params = (Vec<Vec2>, f32, u16, usize);
+impl ControllerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn population(mut self, population: PopulationBuilder) -> Self {
+        self.population = population;
+        self
+    }
+
+    /// Automata replace the genomes of the population, see SimpleBlobController::set_encoding
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
 
-    fn create() -> Self {
-        todo!()
+    pub fn settings(mut self, settings: EvolveSettings) -> Self {
+        self.settings = settings;
+        self
     }
 
-    fn create_like(params: Option<Self::Params>) -> Self {
-        // TODO: fix the hardcoded stuff
-        let mut reference = create_black(12*12);
-        reference.set_quadrant(crate::evolution::square::Quadrant::RightTriangularQuadrant, RGB { r: 255, g: 0, b: 0 });
-        let cost_function: CostFunction<Genome<RGB>> = compare_to_red;
-        let population = SimpleBlobPopulation::create_like(params);
-        SimpleBlobController {
+    /// Automata keep the symmetry of the population
+    pub fn build(&self) -> Result<SimpleBlobController, EvolutionError> {
+        let mut population = self.population.build()?;
+        let mut genealogy = Genealogy::new();
+        genealogy.found(&mut population, 0);
+        let provenance = ProvenanceMap::new();
+        let size = population.len();
+        let (width, height) = population[0].genome.dimensions();
+        let symmetry = population[0].genome.symmetry();
+        let mut reference = create_black(width, height);
+        reference.set_quadrant(Quadrant::RightTriangularQuadrant, RGB { r: 255, g: 0, b: 0 });
+        let cost_function = match_reference(reference, Distance::Channel);
+        // the side limits make room for the genomes, so a resize never crops them
+        let mut settings = self.settings;
+        settings.min_side = settings.min_side.min(width.min(height));
+        settings.max_side = settings.max_side.max(width.max(height));
+        let mut controller = SimpleBlobController {
             population,
             cost_function,
+            encoding: Encoding::Direct,
+            settings,
+            generation: 0,
+            mutations: 0,
+            duration: Duration::ZERO,
+            genealogy,
+            common_ancestor: None,
+            provenance,
+            species: Speciation::new(),
+            island: 0,
+            selection: Selection::CostFunction,
+            ratings: vec![0; size],
+            history: History::new(HISTORY),
+        };
+        controller.remember();
+        if self.encoding != Encoding::Direct {
+            controller.set_encoding(self.encoding)?;
+            controller.set_symmetry(symmetry);
         }
+        Ok(controller)
     }
 }
 
-fn compare_to_red(g: &Genome<RGB>) -> f32 {
-    let mut reference = create_black(12*12);
-    reference.set_quadrant(crate::evolution::square::Quadrant::RightTriangularQuadrant, RGB { r: 255, g: 0, b: 0 });
-    compare_to(g, &reference)
+impl SimpleBlobController {
+    /// Produces the next generation, the velocities follow the new genomes
+    /// A generation which fails leaves the population as it was
+    pub fn evolve(&mut self) -> Result<(), EvolutionError> {
+        let start = Instant::now();
+        let cost_function = self.genome_cost();
+        let births = match self.selection {
+            Selection::CostFunction => SimpleBlobPopulation::evolve(
+                &mut self.population,
+                &cost_function,
+                &self.settings,
+                &mut self.species,
+            ),
+            Selection::Interactive => {
+                // nobody rated the children yet, they are as good as the average blob
+                let costs: Vec<f32> = self.ratings.iter().map(|&r| -(r as f32)).collect();
+                let average = costs.iter().sum::<f32>() / costs.len().max(1) as f32;
+                let unrated: CostFunction<Genome<RGB>> = Rc::new(move |_| average);
+                SimpleBlobPopulation::evolve_rated(
+                    &mut self.population,
+                    costs,
+                    &unrated,
+                    &self.settings,
+                    &mut self.species,
+                )
+            }
+        };
+        let births = match births {
+            Ok(births) => births,
+            Err(e) => {
+                // some children may already have replaced their mothers
+                self.restore(self.generation);
+                return Err(e);
+            }
+        };
+        self.ratings = vec![0; self.population.len()];
+        self.mutations = births.iter().map(|b| b.mutations).sum();
+        self.generation += 1;
+        self.genealogy.record(&mut self.population, &births, self.generation);
+        for birth in births.iter() {
+            self.population[birth.slot].provenance = birth.provenance.clone();
+        }
+        self.provenance.record(&births, &self.population);
+        if self.generation % PRUNE_EVERY == 0 {
+            self.genealogy.prune(&self.living(), self.generation);
+            self.common_ancestor = self.most_recent_common_ancestor();
+        }
+
+        for blob in self.population.iter_mut() {
+            blob.develop(&self.encoding);
+            let velocity = extract_velocity(blob.phenotype());
+            blob.velocity = 10.0 * velocity;
+        }
+        self.remember();
+        self.duration = start.elapsed();
+        Ok(())
+    }
+
+    /// Records the current generation in the history, replacing an earlier record of it
+    fn remember(&mut self) {
+        let snapshot = Snapshot {
+            genomes: self.population.iter().map(|b| b.genome.clone()).collect(),
+            lineages: self.population.iter().map(|b| b.lineage.clone()).collect(),
+            encoding: self.encoding,
+        };
+        self.history.record(self.generation, snapshot);
+    }
+
+    /// Goes back or forth to a remembered generation, returns false if it is not remembered
+    /// The blobs stay where they are, the next generation branches off and forgets the later ones
+    pub fn restore(&mut self, generation: usize) -> bool {
+        let snapshot = match self.history.get(generation) {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.genealogy.remember(&snapshot.lineages);
+        self.encoding = snapshot.encoding;
+        for ((blob, genome), lineage) in self.population.iter_mut().zip(snapshot.genomes).zip(snapshot.lineages) {
+            blob.genome = genome;
+            blob.lineage = lineage;
+            blob.provenance.clear();
+            blob.develop(&self.encoding);
+            blob.velocity = 10.0 * extract_velocity(blob.phenotype());
+        }
+        self.generation = generation;
+        self.ratings = vec![0; self.population.len()];
+        self.provenance = ProvenanceMap::new();
+        self.species.clear();
+        self.common_ancestor = self.most_recent_common_ancestor();
+        true
+    }
+
+    /// Replaces the population with saved genomes, they are repeated if there are fewer genomes than blobs
+    /// The saved genomes have no known parents and the history starts over at the saved generation
+    pub fn load(&mut self, saved: SavedIsland) {
+        self.encoding = saved.encoding;
+        self.generation = saved.generation;
+        for (blob, genome) in self.population.iter_mut().zip(saved.genomes.iter().cycle()) {
+            blob.genome = genome.clone();
+            blob.provenance.clear();
+            blob.develop(&self.encoding);
+            blob.velocity = 10.0 * extract_velocity(blob.phenotype());
+        }
+        self.genealogy = Genealogy::new();
+        self.genealogy.found(&mut self.population, self.generation);
+        self.common_ancestor = None;
+        self.ratings = vec![0; self.population.len()];
+        self.provenance = ProvenanceMap::new();
+        self.species.clear();
+        self.history = History::new(self.history.capacity);
+        self.remember();
+    }
+
+    /// Makes the population the founders of a run which is at generation already, e.g. a new island
+    /// The history starts over at that generation
+    pub fn start_at(&mut self, generation: usize) {
+        self.generation = generation;
+        self.genealogy = Genealogy::new();
+        self.genealogy.found(&mut self.population, generation);
+        self.common_ancestor = None;
+        self.history = History::new(self.history.capacity);
+        self.remember();
+    }
+
+    /// Raises the rating of a blob by one, the highest rating wraps around to 0
+    pub fn rate(&mut self, blob: usize) {
+        if let Some(rating) = self.ratings.get_mut(blob) {
+            *rating = (*rating + 1) % (MAX_RATING + 1);
+        }
+    }
+
+    /// Rates genomes by what they grow into
+    pub fn genome_cost(&self) -> CostFunction<Genome<RGB>> {
+        self.encoding.developed(self.cost_function.clone())
+    }
+
+    /// The genomes of the k creatures with lowest cost
+    pub fn best(&self, k: usize) -> Vec<Genome<RGB>> {
+        let cost_function = self.genome_cost();
+        let mut ranking: Vec<(f32, &Genome<RGB>)> = self
+            .population
+            .iter()
+            .map(|b| (cost_function(&b.genome), &b.genome))
+            .collect();
+        ranking.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        ranking.into_iter().take(k).map(|(_, g)| g.clone()).collect()
+    }
+
+    /// The symmetry of the genomes of this island
+    pub fn symmetry(&self) -> Symmetry {
+        self.population[0].genome.symmetry()
+    }
+
+    /// Folds every genome into the new symmetry, the left and bottom parts of the genomes are kept
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        for blob in self.population.iter_mut() {
+            blob.genome = blob.genome.folded(symmetry);
+            blob.provenance.clear();
+            blob.develop(&self.encoding);
+        }
+        self.provenance = ProvenanceMap::new();
+        self.species.clear();
+        self.remember();
+    }
+
+    /// Switches the encoding of every genome
+    /// Direct genomes are replaced by random automata, developed genomes become the grid they grew into
+    pub fn set_encoding(&mut self, encoding: Encoding) -> Result<(), EvolutionError> {
+        if let Encoding::Automaton { width, height, .. } = encoding {
+            if width == 0 || height == 0 {
+                return Err(EvolutionError::EmptyGenome { width, height });
+            }
+        }
+        for blob in self.population.iter_mut() {
+            match (self.encoding, encoding) {
+                (Encoding::Direct, Encoding::Automaton { .. }) => {
+                    let (width, height) = AUTOMATON_GENOME;
+                    blob.genome = GenomeBuilder::new().dimensions(width, height).template(Template::Random).build()?;
+                }
+                (Encoding::Automaton { .. }, Encoding::Direct) => blob.genome = blob.phenotype().clone(),
+                _ => {}
+            }
+            blob.provenance.clear();
+            blob.develop(&encoding);
+        }
+        self.encoding = encoding;
+        self.provenance = ProvenanceMap::new();
+        self.species.clear();
+        self.remember();
+        Ok(())
+    }
+
+    /// Immigrants replace the genomes of the creatures with highest cost
+    /// They have no known parents on this island and take on its symmetry
+    /// Islands are expected to share their encoding
+    pub fn receive(&mut self, genomes: Vec<Genome<RGB>>) {
+        let symmetry = self.symmetry();
+        let cost_function = self.genome_cost();
+        let mut ranking: Vec<(f32, usize)> = self
+            .population
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (cost_function(&b.genome), i))
+            .collect();
+        ranking.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        for ((_, slot), genome) in ranking.into_iter().zip(genomes) {
+            let blob = &mut self.population[slot];
+            blob.genome = genome.folded(symmetry);
+            blob.provenance.clear();
+            blob.develop(&self.encoding);
+            self.genealogy.found_creature(blob, self.generation);
+        }
+        self.remember();
+    }
+
+    /// The ids of the current population
+    pub fn living(&self) -> Vec<u64> {
+        self.population.iter().map(|b| b.lineage.id).collect()
+    }
+
+    pub fn most_recent_common_ancestor(&self) -> Option<u64> {
+        self.genealogy.most_recent_common_ancestor(&self.living())
+    }
+
+    /// Writes the family tree of the living population, as JSON for .json files and as DOT otherwise
+    pub fn export_genealogy<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let living = self.living();
+        self.genealogy.prune(&living, self.generation);
+        let content = match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("json") => self.genealogy.to_json(),
+            _ => self.genealogy.to_dot(&living),
+        };
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+    }
+
+    /// Writes the provenance counts of every gene position as CSV
+    pub fn export_provenance<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.provenance.to_csv())
+    }
+
+    pub fn metrics(&self) -> GenerationMetrics {
+        let mut metrics = GenerationMetrics::measure(
+            &self.population,
+            &self.genome_cost(),
+            self.generation,
+            self.mutations,
+            self.duration,
+        );
+        metrics.island = self.island;
+        metrics
+    }
+
+    /// Moves every blob by one physics tick
+    pub fn step(&mut self) {
+        for blob in self.population.iter_mut() {
+            blob.update();
+        }
+    }
+
+    /// Replaces the cost function by a comparison against the image at path
+    pub fn set_target<P: AsRef<Path>>(&mut self, path: P, distance: Distance) -> Result<(), ImageError> {
+        let (width, height) = self.population[0].phenotype().dimensions();
+        let reference = load_target(path, width, height)?;
+        self.cost_function = match_reference(reference, distance);
+        Ok(())
+    }
 }
@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::PathBuf;
+
+use nannou::image::{ImageResult, Rgb, RgbImage};
+
+use crate::{
+    evolution::{blob::RGB, gene::Genome, population::SimpleBlobPopulation, square::Square},
+    simulation::controller::SimpleBlobController,
+};
+
+/// Renders a Genome<RGB> as an image where every gene becomes a scale * scale pixel block
+/// The start of the genome is the bottom row, just like it is drawn in the viewer
+pub fn genome_to_image(genome: &Genome<RGB>, scale: u32) -> RgbImage {
+    let (width, height) = genome.dimensions();
+    let mut image = RgbImage::new(width as u32 * scale, height as u32 * scale);
+    paint_genome(&mut image, genome, scale, (0, 0));
+    image
+}
+
+/// The size of a tile of a contact sheet, every genome fits into it
+pub fn tile_dimensions(population: &SimpleBlobPopulation) -> (u32, u32) {
+    population.iter().fold((0, 0), |(w, h), b| {
+        let (width, height) = b.phenotype().dimensions();
+        (w.max(width as u32), h.max(height as u32))
+    })
+}
+
+/// Tiles all genomes of a population into a single image, row by row
+/// Every genome sits in the top left corner of its tile
+pub fn contact_sheet(population: &SimpleBlobPopulation, columns: u32, scale: u32, padding: u32) -> RgbImage {
+    let (width, height) = tile_dimensions(population);
+    let columns = columns.clamp(1, population.len().max(1) as u32);
+    let rows = (population.len() as u32 + columns - 1) / columns;
+    let tile = (width * scale + padding, height * scale + padding);
+    let mut image = RgbImage::from_pixel(
+        columns * tile.0 + padding,
+        rows * tile.1 + padding,
+        Rgb([28, 31, 33]),
+    );
+    for (i, blob) in population.iter().enumerate() {
+        let i = i as u32;
+        let origin = (padding + (i % columns) * tile.0, padding + (i / columns) * tile.1);
+        paint_genome(&mut image, blob.phenotype(), scale, origin);
+    }
+    image
+}
+
+fn paint_genome(image: &mut RgbImage, genome: &Genome<RGB>, scale: u32, origin: (u32, u32)) {
+    let (width, height) = genome.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = genome.get((x, y)).as_color();
+            // image rows grow downwards, genome rows grow upwards
+            let top = origin.1 + (height - 1 - y) as u32 * scale;
+            let left = origin.0 + x as u32 * scale;
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    image.put_pixel(left + dx, top + dy, Rgb([r, g, b]));
+                }
+            }
+        }
+    }
+}
+
+/// Writes genomes and contact sheets into a directory
+/// The file format follows the extension, so "png" and "ppm" are both supported
+pub struct Exporter {
+    pub directory: PathBuf,
+    pub extension: String,
+    /// export every n-th generation, 0 disables periodic exports
+    pub every: usize,
+    pub scale: u32,
+    pub columns: u32,
+}
+
+impl Exporter {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Exporter {
+            directory: directory.into(),
+            extension: "png".to_string(),
+            every: 0,
+            scale: 8,
+            columns: 8,
+        }
+    }
+
+    fn path(&self, name: String) -> ImageResult<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+        Ok(self.directory.join(format!("{}.{}", name, self.extension)))
+    }
+
+    pub fn export_genome(&self, genome: &Genome<RGB>, name: String) -> ImageResult<PathBuf> {
+        let path = self.path(name)?;
+        genome_to_image(genome, self.scale).save(&path)?;
+        Ok(path)
+    }
+
+    /// Files of a generation are named after the generation and the island
+    fn name(controller: &SimpleBlobController) -> String {
+        format!("generation_{:06}_island_{}", controller.generation, controller.island)
+    }
+
+    pub fn export_genomes(&self, controller: &SimpleBlobController) -> ImageResult<()> {
+        let name = Self::name(controller);
+        for (i, blob) in controller.population.iter().enumerate() {
+            self.export_genome(blob.phenotype(), format!("{}_blob_{:03}", name, i))?;
+        }
+        Ok(())
+    }
+
+    pub fn export_sheet(&self, controller: &SimpleBlobController) -> ImageResult<PathBuf> {
+        let path = self.path(format!("{}_sheet", Self::name(controller)))?;
+        contact_sheet(&controller.population, self.columns, self.scale, self.scale).save(&path)?;
+        Ok(path)
+    }
+
+    /// Exports a contact sheet if the generation is due
+    pub fn on_generation(&self, controller: &SimpleBlobController) -> ImageResult<Option<PathBuf>> {
+        if self.every == 0 || controller.generation % self.every != 0 {
+            return Ok(None);
+        }
+        self.export_sheet(controller).map(Some)
+    }
+}
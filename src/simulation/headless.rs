@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use crate::{
+    info,
+    simulation::{
+        export::Exporter,
+        islands::Archipelago,
+        recorder::Recorder,
+        termination::{RunOutcome, Termination},
+    },
+    statistics::metrics::MetricsSink,
+};
+
+/// Runs the simulation without a window until a termination criterion is met
+pub struct Headless {
+    pub termination: Termination,
+    pub exporter: Exporter,
+    pub recorder: Recorder,
+    pub metrics: Option<MetricsSink>,
+}
+
+impl Headless {
+    /// Fails if a generation fails or an export could not be written
+    pub fn run(&mut self, archipelago: &mut Archipelago) -> Result<RunOutcome, Box<dyn Error>> {
+        self.termination.restart(archipelago);
+        let outcome = loop {
+            archipelago.evolve()?;
+            archipelago.step();
+            for island in archipelago.islands.iter() {
+                if let Some(path) = self.exporter.on_generation(island)? {
+                    info!("exported {}", path.display());
+                }
+                if let Some(metrics) = &mut self.metrics {
+                    metrics.record(&island.metrics())?;
+                }
+            }
+            self.recorder.rasterise(archipelago)?;
+            if let Some(outcome) = self.termination.check(archipelago) {
+                break outcome;
+            }
+        };
+        if let Some(metrics) = &mut self.metrics {
+            metrics.flush()?;
+        }
+        if self.recorder.recording {
+            info!("recorded {} frames", self.recorder.frames());
+        }
+        let path = self.exporter.export_genome(&outcome.best, "best".to_string())?;
+        info!("exported the best genome to {}", path.display());
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use crate::evolution::{blob::RGB, development::Encoding, gene::Genome, lineage::Lineage};
+
+/// How the genome of a single blob changed against the previous generation
+#[derive(Debug, Clone)]
+enum Change {
+    /// only these stored genes differ
+    Genes(Vec<(usize, RGB)>),
+    /// the genome changed its size or symmetry
+    Genome(Genome<RGB>),
+}
+
+#[derive(Debug, Clone)]
+enum Genomes {
+    /// a full copy of every genome
+    Key(Vec<Genome<RGB>>),
+    /// the blobs which changed, every other blob kept its genome
+    Delta(Vec<(usize, Change)>),
+}
+
+/// A generation as it is remembered, lineages are small and kept whole
+#[derive(Debug, Clone)]
+struct Entry {
+    genomes: Genomes,
+    lineages: Vec<Lineage>,
+    encoding: Encoding,
+}
+
+/// The genomes and lineages of a population at the end of a generation
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub genomes: Vec<Genome<RGB>>,
+    pub lineages: Vec<Lineage>,
+    /// what the genomes grow into
+    pub encoding: Encoding,
+}
+
+/// A bounded ring of past generations, most of them delta encoded against their predecessor
+pub struct History {
+    /// the most generations which are remembered, the oldest are forgotten first
+    pub capacity: usize,
+    /// a full copy is kept this often, so restoring never replays more deltas than this
+    pub keyframe_every: usize,
+    /// the generation of the first entry
+    first: usize,
+    entries: VecDeque<Entry>,
+    /// the genomes of the generation recorded last, the next generation is a delta against them
+    last: Option<(usize, Vec<Genome<RGB>>)>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            capacity,
+            keyframe_every: 32,
+            first: 0,
+            entries: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    /// The generations which can be restored
+    pub fn range(&self) -> Option<RangeInclusive<usize>> {
+        match self.entries.len() {
+            0 => None,
+            len => Some(self.first..=self.first + len - 1),
+        }
+    }
+
+    /// Remembers a generation, every later generation is forgotten, so recording after a restore branches the run
+    /// Recording a generation twice replaces it, e.g. after migrants arrived
+    pub fn record(&mut self, generation: usize, snapshot: Snapshot) {
+        // only a branch or a repeated generation has to replay deltas to find its predecessor
+        let previous = match (self.last.take(), generation.checked_sub(1)) {
+            (Some((last, genomes)), Some(before)) if last == before => Some(genomes),
+            (_, Some(before)) => self.get(before).map(|previous| previous.genomes),
+            _ => None,
+        };
+        match self.range() {
+            Some(range) if range.contains(&generation) || generation == range.end() + 1 => {
+                self.entries.truncate(generation - self.first);
+            }
+            _ => {
+                self.entries.clear();
+                self.first = generation;
+            }
+        }
+
+        let genomes = match previous {
+            Some(previous)
+                if generation % self.keyframe_every.max(1) != 0 && previous.len() == snapshot.genomes.len() =>
+            {
+                Genomes::Delta(delta(&previous, &snapshot.genomes))
+            }
+            _ => Genomes::Key(snapshot.genomes.clone()),
+        };
+        self.last = Some((generation, snapshot.genomes));
+        self.entries.push_back(Entry {
+            genomes,
+            lineages: snapshot.lineages,
+            encoding: snapshot.encoding,
+        });
+
+        while self.entries.len() > self.capacity.max(1) {
+            let Some(evicted) = self.entries.pop_front() else {
+                break;
+            };
+            self.first += 1;
+            // the new first entry has no predecessor to be a delta of, the evicted copy becomes its copy
+            if let (Genomes::Key(mut genomes), Some(next)) = (evicted.genomes, self.entries.front_mut()) {
+                if let Genomes::Delta(changes) = &next.genomes {
+                    apply(&mut genomes, changes);
+                    next.genomes = Genomes::Key(genomes);
+                }
+            }
+        }
+    }
+
+    /// Replays the deltas since the last full copy before the generation
+    pub fn get(&self, generation: usize) -> Option<Snapshot> {
+        let index = generation.checked_sub(self.first).filter(|&i| i < self.entries.len())?;
+        let key = (0..=index)
+            .rev()
+            .find(|&i| matches!(self.entries[i].genomes, Genomes::Key(_)))?;
+        let mut genomes = match &self.entries[key].genomes {
+            Genomes::Key(genomes) => genomes.clone(),
+            Genomes::Delta(_) => unreachable!(),
+        };
+        for entry in self.entries.range(key + 1..=index) {
+            if let Genomes::Delta(changes) = &entry.genomes {
+                apply(&mut genomes, changes);
+            }
+        }
+        let entry = &self.entries[index];
+        Some(Snapshot {
+            genomes,
+            lineages: entry.lineages.clone(),
+            encoding: entry.encoding,
+        })
+    }
+
+    /// The amount of genes which are stored, and how many full copies of every generation would need
+    pub fn stored_genes(&self) -> (usize, usize) {
+        let mut stored = 0;
+        let mut full = 0;
+        let mut population = 0;
+        for entry in self.entries.iter() {
+            match &entry.genomes {
+                Genomes::Key(genomes) => {
+                    population = genomes.iter().map(|g| g.len()).sum();
+                    stored += population;
+                }
+                Genomes::Delta(changes) => {
+                    stored += changes
+                        .iter()
+                        .map(|(_, change)| match change {
+                            Change::Genes(genes) => genes.len(),
+                            Change::Genome(genome) => genome.len(),
+                        })
+                        .sum::<usize>();
+                }
+            }
+            full += population;
+        }
+        (stored, full)
+    }
+}
+
+fn delta(previous: &[Genome<RGB>], next: &[Genome<RGB>]) -> Vec<(usize, Change)> {
+    previous
+        .iter()
+        .zip(next)
+        .enumerate()
+        .filter_map(|(blob, (before, after))| {
+            if before.dimensions() != after.dimensions() || before.symmetry() != after.symmetry() {
+                return Some((blob, Change::Genome(after.clone())));
+            }
+            let genes: Vec<(usize, RGB)> = before
+                .iter()
+                .zip(after.iter())
+                .enumerate()
+                .filter(|(_, (a, b))| a != b)
+                .map(|(at, (_, &b))| (at, b))
+                .collect();
+            (!genes.is_empty()).then(|| (blob, Change::Genes(genes)))
+        })
+        .collect()
+}
+
+fn apply(genomes: &mut [Genome<RGB>], changes: &[(usize, Change)]) {
+    for (blob, change) in changes {
+        match change {
+            Change::Genes(genes) => {
+                for &(at, gene) in genes {
+                    genomes[*blob][at] = gene;
+                }
+            }
+            Change::Genome(genome) => genomes[*blob] = genome.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genome(width: usize, height: usize, red: u8) -> Genome<RGB> {
+        Genome::new(vec![RGB { r: red, g: 0, b: 0 }; width * height], width, height)
+    }
+
+    fn snapshot(genomes: &[Genome<RGB>]) -> Snapshot {
+        Snapshot {
+            genomes: genomes.to_vec(),
+            lineages: vec![Lineage::default(); genomes.len()],
+            encoding: Encoding::Direct,
+        }
+    }
+
+    fn assert_same(restored: &[Genome<RGB>], expected: &[Genome<RGB>]) {
+        assert_eq!(restored.len(), expected.len());
+        for (a, b) in restored.iter().zip(expected) {
+            assert_eq!(a.dimensions(), b.dimensions());
+            assert_eq!(a.symmetry(), b.symmetry());
+            assert!(a.iter().eq(b.iter()));
+        }
+    }
+
+    /// Every generation changes a gene of one blob, some also resize a blob
+    fn generations(amount: usize) -> Vec<Vec<Genome<RGB>>> {
+        let mut genomes = vec![genome(3, 3, 0); 4];
+        (0..amount)
+            .map(|generation| {
+                let blob = generation % genomes.len();
+                if generation % 5 == 3 {
+                    genomes[blob] = genome(2 + generation % 3, 3, generation as u8);
+                } else {
+                    genomes[blob][generation % 6] = RGB { r: 0, g: generation as u8, b: 1 };
+                }
+                genomes.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn restores_every_generation_across_keyframes() {
+        let mut history = History::new(100);
+        history.keyframe_every = 4;
+        let expected = generations(20);
+        for (generation, genomes) in expected.iter().enumerate() {
+            history.record(generation, snapshot(genomes));
+        }
+        assert_eq!(history.range(), Some(0..=19));
+        for (generation, genomes) in expected.iter().enumerate() {
+            assert_same(&history.get(generation).unwrap().genomes, genomes);
+        }
+        let (stored, full) = history.stored_genes();
+        assert!(stored < full);
+    }
+
+    #[test]
+    fn eviction_keeps_the_newest_generations_restorable() {
+        let mut history = History::new(6);
+        history.keyframe_every = 4;
+        let expected = generations(15);
+        for (generation, genomes) in expected.iter().enumerate() {
+            history.record(generation, snapshot(genomes));
+        }
+        assert_eq!(history.range(), Some(9..=14));
+        assert!(history.get(8).is_none());
+        for (generation, genomes) in expected.iter().enumerate().skip(9) {
+            assert_same(&history.get(generation).unwrap().genomes, genomes);
+        }
+    }
+
+    #[test]
+    fn recording_an_earlier_generation_branches() {
+        let mut history = History::new(100);
+        history.keyframe_every = 4;
+        let expected = generations(10);
+        for (generation, genomes) in expected.iter().enumerate() {
+            history.record(generation, snapshot(genomes));
+        }
+        let mut branch = expected[5].clone();
+        branch[2][0] = RGB { r: 9, g: 9, b: 9 };
+        history.record(6, snapshot(&branch));
+        assert_eq!(history.range(), Some(0..=6));
+        assert_same(&history.get(5).unwrap().genomes, &expected[5]);
+        assert_same(&history.get(6).unwrap().genomes, &branch);
+
+        let mut next = branch.clone();
+        next[3][1] = RGB { r: 1, g: 2, b: 3 };
+        history.record(7, snapshot(&next));
+        assert_same(&history.get(7).unwrap().genomes, &next);
+    }
+
+    #[test]
+    fn recording_a_generation_twice_replaces_it() {
+        let mut history = History::new(100);
+        let expected = generations(3);
+        for (generation, genomes) in expected.iter().enumerate() {
+            history.record(generation, snapshot(genomes));
+        }
+        let mut migrated = expected[2].clone();
+        migrated[0] = genome(4, 4, 200);
+        history.record(2, snapshot(&migrated));
+        assert_eq!(history.range(), Some(0..=2));
+        assert_same(&history.get(1).unwrap().genomes, &expected[1]);
+        assert_same(&history.get(2).unwrap().genomes, &migrated);
+    }
+}
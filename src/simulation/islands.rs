@@ -0,0 +1,280 @@
+use std::ops::RangeInclusive;
+
+use nannou::prelude::*;
+use rand::Rng;
+
+use crate::{
+    camera::{blob_at, population_bounds, union},
+    error,
+    evolution::{blob::RGB, error::EvolutionError, gene::Genome},
+    simulation::controller::SimpleBlobController,
+    util::rng,
+    Nannou, View,
+};
+
+/// Which islands send migrants to which
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// island i sends to island i + 1
+    Ring,
+    /// every island sends to every other island
+    Full,
+    /// every island sends to a random other island
+    Random,
+}
+
+impl Topology {
+    pub const ALL: [Topology; 3] = [Topology::Ring, Topology::Full, Topology::Random];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topology::Ring => "ring",
+            Topology::Full => "full",
+            Topology::Random => "random",
+        }
+    }
+
+    /// The islands receiving migrants from island `from`
+    pub fn targets(&self, from: usize, islands: usize) -> Vec<usize> {
+        if islands < 2 {
+            return Vec::new();
+        }
+        match self {
+            Topology::Ring => vec![(from + 1) % islands],
+            Topology::Full => (0..islands).filter(|&i| i != from).collect(),
+            Topology::Random => {
+                // skip over the sending island
+                let target = rng().gen_range(0..islands - 1);
+                vec![if target >= from { target + 1 } else { target }]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// migrate every n-th generation, 0 disables migration
+    pub every: usize,
+    /// the amount of best individuals sent to every target
+    pub amount: usize,
+    pub topology: Topology,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        Migration {
+            every: 25,
+            amount: 2,
+            topology: Topology::Ring,
+        }
+    }
+}
+
+/// Several populations which evolve on their own, each with its own cost function and settings,
+/// and which exchange their best individuals from time to time
+pub struct Archipelago {
+    pub islands: Vec<SimpleBlobController>,
+    pub migration: Migration,
+    /// the island shown in the user interface
+    pub selected: usize,
+}
+
+impl Archipelago {
+    pub fn new(mut islands: Vec<SimpleBlobController>, migration: Migration) -> Self {
+        assert!(!islands.is_empty(), "An archipelago needs at least one island");
+        for (i, island) in islands.iter_mut().enumerate() {
+            island.island = i;
+        }
+        Archipelago {
+            islands,
+            migration,
+            selected: 0,
+        }
+    }
+
+    /// Adds an island after the others, every other island keeps evolving where it is
+    /// The new island joins at the shared generation, so it can be undone no further than that
+    pub fn add(&mut self, mut island: SimpleBlobController) {
+        island.island = self.islands.len();
+        island.start_at(self.generation());
+        self.islands.push(island);
+    }
+
+    /// The area covered by all islands in world space
+    pub fn bounds(&self) -> Option<Rect> {
+        self.islands
+            .iter()
+            .filter_map(|island| population_bounds(&island.population))
+            .reduce(union)
+    }
+
+    /// Returns the island and the index of the blob at the world position
+    pub fn blob_at(&self, at: Point2) -> Option<(usize, usize)> {
+        self.islands
+            .iter()
+            .enumerate()
+            .find_map(|(i, island)| blob_at(&island.population, at).map(|blob| (i, blob)))
+    }
+
+    pub fn selected(&self) -> &SimpleBlobController {
+        &self.islands[self.selected]
+    }
+
+    pub fn selected_mut(&mut self) -> &mut SimpleBlobController {
+        &mut self.islands[self.selected]
+    }
+
+    /// All islands evolve in lockstep, so they share the generation
+    pub fn generation(&self) -> usize {
+        self.islands[0].generation
+    }
+
+    /// Fails with the first island which could not evolve, the islands before it go back to where they were
+    pub fn evolve(&mut self) -> Result<(), EvolutionError> {
+        let generation = self.generation();
+        for i in 0..self.islands.len() {
+            if let Err(e) = self.islands[i].evolve() {
+                // the failed island rolled itself back already
+                for island in self.islands[..i].iter_mut() {
+                    island.restore(generation);
+                }
+                return Err(e);
+            }
+        }
+        let every = self.migration.every;
+        if every > 0 && self.generation() % every == 0 {
+            self.migrate();
+        }
+        Ok(())
+    }
+
+    /// Goes back one generation on every island, returns false if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.generation() {
+            0 => false,
+            generation => self.restore(generation - 1),
+        }
+    }
+
+    /// The generations which every island remembers
+    pub fn history(&self) -> Option<RangeInclusive<usize>> {
+        self.islands.iter().try_fold(0..=usize::MAX, |range, island| {
+            let other = island.history.range()?;
+            Some(*range.start().max(other.start())..=*range.end().min(other.end()))
+        })
+        .filter(|range| !range.is_empty())
+    }
+
+    /// Moves every island to a remembered generation, see SimpleBlobController::restore
+    pub fn restore(&mut self, generation: usize) -> bool {
+        if !self.history().map_or(false, |range| range.contains(&generation)) {
+            return false;
+        }
+        for island in self.islands.iter_mut() {
+            island.restore(generation);
+        }
+        true
+    }
+
+    pub fn step(&mut self) {
+        for island in self.islands.iter_mut() {
+            island.step();
+        }
+    }
+
+    /// Copies the best individuals of every island over the worst individuals of its targets
+    pub fn migrate(&mut self) {
+        let amount = self.migration.amount;
+        if amount == 0 {
+            return;
+        }
+        // every island picks its emigrants before anybody arrives
+        let emigrants: Vec<Vec<Genome<RGB>>> = self.islands.iter().map(|island| island.best(amount)).collect();
+        let mut immigrants: Vec<Vec<Genome<RGB>>> = vec![Vec::new(); self.islands.len()];
+        for (from, genomes) in emigrants.into_iter().enumerate() {
+            for to in self.migration.topology.targets(from, self.islands.len()) {
+                immigrants[to].extend(genomes.iter().cloned());
+            }
+        }
+        for (island, genomes) in self.islands.iter_mut().zip(immigrants) {
+            island.receive(genomes);
+        }
+    }
+}
+
+impl Nannou for Archipelago {
+    fn draw(&self, draw: &Draw, view: &View) {
+        for island in self.islands.iter() {
+            island.draw(draw, view);
+        }
+        if self.islands.len() < 2 {
+            return;
+        }
+        for (i, island) in self.islands.iter().enumerate() {
+            if let Some(bounds) = population_bounds(&island.population) {
+                let color = if i == self.selected { WHITE } else { GRAY };
+                draw.rect()
+                    .xy(view.transform(bounds.xy()))
+                    .wh(bounds.wh() * view.camera.zoom)
+                    .no_fill()
+                    .stroke(color)
+                    .stroke_weight(1.0);
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        if let Err(e) = self.evolve() {
+            error!("failed to evolve: {}", e);
+        }
+        self.step();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evolution::builder::{GenomeBuilder, PopulationBuilder},
+        simulation::controller::ControllerBuilder,
+    };
+
+    fn island() -> SimpleBlobController {
+        let population = PopulationBuilder::new().size(6).genome(GenomeBuilder::new().dimensions(4, 4));
+        ControllerBuilder::new().population(population).build().unwrap()
+    }
+
+    #[test]
+    fn an_added_island_joins_at_the_shared_generation() {
+        let mut archipelago = Archipelago::new(vec![island()], Migration::default());
+        for _ in 0..3 {
+            archipelago.evolve().unwrap();
+        }
+        archipelago.add(island());
+        assert!(archipelago.islands.iter().all(|island| island.generation == 3));
+        assert_eq!(archipelago.history(), Some(3..=3));
+
+        archipelago.evolve().unwrap();
+        archipelago.evolve().unwrap();
+        assert_eq!(archipelago.history(), Some(3..=5));
+        assert!(archipelago.undo());
+        assert!(archipelago.islands.iter().all(|island| island.generation == 4));
+        assert!(archipelago.restore(3));
+        assert!(!archipelago.restore(2));
+    }
+
+    #[test]
+    fn a_failed_generation_leaves_every_island_where_it_was() {
+        let mut archipelago = Archipelago::new(vec![island(), island(), island()], Migration::default());
+        archipelago.evolve().unwrap();
+        archipelago.evolve().unwrap();
+        let genes = |island: &SimpleBlobController| -> Vec<RGB> {
+            island.population.iter().flat_map(|blob| blob.genome.iter().copied()).collect()
+        };
+        let before = genes(&archipelago.islands[0]);
+        archipelago.islands[1].population.clear();
+        assert_eq!(archipelago.evolve(), Err(EvolutionError::EmptyPopulation));
+        assert!(archipelago.islands.iter().all(|island| island.generation == 2));
+        assert_eq!(genes(&archipelago.islands[0]), before);
+    }
+}
@@ -1 +1,10 @@
+pub mod atlas;
 pub mod controller;
+pub mod export;
+pub mod headless;
+pub mod history;
+pub mod islands;
+pub mod recorder;
+pub mod scheduler;
+pub mod snapshot;
+pub mod termination;
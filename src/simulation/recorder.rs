@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::PathBuf;
+
+use nannou::geom::Point2;
+use nannou::image::{ImageResult, Rgb, RgbImage};
+use nannou::prelude::Window;
+
+use crate::{evolution::square::Square, simulation::islands::Archipelago};
+
+/// Settings of a recording
+pub struct RecordingConfig {
+    pub directory: PathBuf,
+    pub extension: String,
+    /// resolution of the software rasteriser, the window capture uses the window size
+    pub width: u32,
+    pub height: u32,
+    /// record every n-th frame
+    pub stride: u64,
+    /// camera of the software rasteriser
+    pub center: Point2,
+    pub zoom: f32,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig {
+            directory: PathBuf::from("recording"),
+            extension: "png".to_string(),
+            width: 640,
+            height: 360,
+            stride: 1,
+            center: Point2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Writes rendered frames into a numbered image sequence
+pub struct Recorder {
+    pub config: RecordingConfig,
+    pub recording: bool,
+    /// render with the software rasteriser instead of capturing the window
+    pub software: bool,
+    ticks: u64,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn new(config: RecordingConfig) -> Self {
+        Recorder {
+            config,
+            recording: false,
+            software: false,
+            ticks: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Counts a rendered frame and returns the path of the next image if it should be recorded
+    fn next_path(&mut self) -> ImageResult<Option<PathBuf>> {
+        if !self.recording {
+            return Ok(None);
+        }
+        self.ticks += 1;
+        if (self.ticks - 1) % self.config.stride.max(1) != 0 {
+            return Ok(None);
+        }
+        fs::create_dir_all(&self.config.directory)?;
+        let name = format!("frame_{:06}.{}", self.frames, self.config.extension);
+        self.frames += 1;
+        Ok(Some(self.config.directory.join(name)))
+    }
+
+    /// Captures the next frame the window renders
+    pub fn capture(&mut self, window: &Window) -> ImageResult<Option<PathBuf>> {
+        let path = self.next_path()?;
+        if let Some(path) = &path {
+            window.capture_frame(path);
+        }
+        Ok(path)
+    }
+
+    /// Renders every island without a GPU
+    pub fn rasterise(&mut self, archipelago: &Archipelago) -> ImageResult<Option<PathBuf>> {
+        let path = self.next_path()?;
+        if let Some(path) = &path {
+            let config = &self.config;
+            rasterise(archipelago, config.width, config.height, config.center, config.zoom).save(path)?;
+        }
+        Ok(path)
+    }
+}
+
+/// Software rasteriser drawing every gene as a square, just like Blob::draw_rect
+pub fn rasterise(archipelago: &Archipelago, width: u32, height: u32, center: Point2, zoom: f32) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, Rgb([28, 31, 33]));
+    let (half_width, half_height) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    for blob in archipelago.islands.iter().flat_map(|island| island.population.iter()) {
+        let genome = blob.phenotype();
+        let (columns, rows) = genome.dimensions();
+        let size = blob.size() * zoom;
+        let at = (blob.position() - center) * zoom;
+        let bottom_left = (
+            at.x - (columns as f32 / 2.) * size + size / 2.,
+            at.y - (rows as f32 / 2.) * size + size / 2.,
+        );
+
+        for y in 0..rows {
+            for x in 0..columns {
+                // center of the gene in world space, then flipped into image space
+                let gene_x = bottom_left.0 + x as f32 * size + half_width;
+                let gene_y = half_height - (bottom_left.1 + y as f32 * size);
+                let left = (gene_x - size / 2.).round().max(0.0) as u32;
+                let right = ((gene_x + size / 2.).round().max(0.0) as u32).min(width);
+                let top = (gene_y - size / 2.).round().max(0.0) as u32;
+                let bottom = ((gene_y + size / 2.).round().max(0.0) as u32).min(height);
+
+                let (r, g, b) = genome.get((x, y)).as_color();
+                for py in top..bottom {
+                    for px in left..right {
+                        image.put_pixel(px, py, Rgb([r, g, b]));
+                    }
+                }
+            }
+        }
+    }
+    image
+}
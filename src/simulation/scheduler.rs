@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use crate::{error, simulation::islands::Archipelago};
+
+/// Decides how many physics ticks and generations run during a rendered frame
+/// Physics advances with a fixed timestep, evolution with its own rate, both scaled by fast_forward
+pub struct Scheduler {
+    /// simulated seconds per physics tick
+    pub timestep: f32,
+    pub generations_per_second: f32,
+    /// simulated time per real time, so K runs roughly K times the ticks per frame
+    pub fast_forward: u32,
+    /// maximum time spent simulating per frame, the rest of the backlog is dropped
+    pub budget: Duration,
+    pub paused: bool,
+    /// generations only run when they are requested, e.g. while a person rates the blobs
+    pub manual: bool,
+    requested: u32,
+    physics_time: f32,
+    evolution_time: f32,
+}
+
+/// What happened during a single call to Scheduler::advance
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ticks {
+    pub physics: u32,
+    pub generations: u32,
+    /// true if the budget ran out before the backlog was worked off
+    pub throttled: bool,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            timestep: 1.0 / 60.0,
+            generations_per_second: 60.0,
+            fast_forward: 1,
+            budget: Duration::from_millis(12),
+            paused: false,
+            manual: false,
+            requested: 0,
+            physics_time: 0.0,
+            evolution_time: 0.0,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Asks for one more generation while the scheduler is manual
+    pub fn request(&mut self) {
+        self.requested += 1;
+    }
+
+    /// Runs all ticks which are due after since_last, on_generation is called after each generation
+    /// The scheduler pauses as soon as on_generation returns false or a generation fails
+    pub fn advance<F>(&mut self, archipelago: &mut Archipelago, since_last: Duration, mut on_generation: F) -> Ticks
+    where
+        F: FnMut(&Archipelago) -> bool,
+    {
+        let mut ticks = Ticks::default();
+        if self.paused {
+            return ticks;
+        }
+        let start = Instant::now();
+        let elapsed = since_last.as_secs_f32() * self.fast_forward as f32;
+        self.physics_time += elapsed;
+        if self.manual {
+            self.evolution_time = self.requested as f32;
+            self.requested = 0;
+        } else {
+            self.evolution_time += elapsed * self.generations_per_second;
+        }
+
+        loop {
+            let physics_due = self.physics_time >= self.timestep;
+            let evolution_due = self.evolution_time >= 1.0;
+            if !physics_due && !evolution_due {
+                break;
+            }
+            if start.elapsed() > self.budget {
+                ticks.throttled = true;
+                self.physics_time = self.physics_time.min(self.timestep);
+                self.evolution_time = self.evolution_time.min(1.0);
+                break;
+            }
+            // interleave both clocks, whichever is further behind runs first
+            if evolution_due && (!physics_due || self.evolution_time >= self.physics_time / self.timestep) {
+                if let Err(e) = archipelago.evolve() {
+                    error!("evolution paused: {}", e);
+                    self.paused = true;
+                    self.evolution_time = 0.0;
+                    break;
+                }
+                self.evolution_time -= 1.0;
+                ticks.generations += 1;
+                if !on_generation(archipelago) {
+                    self.paused = true;
+                    self.physics_time = 0.0;
+                    self.evolution_time = 0.0;
+                    break;
+                }
+            } else {
+                archipelago.step();
+                self.physics_time -= self.timestep;
+                ticks.physics += 1;
+            }
+        }
+        ticks
+    }
+}
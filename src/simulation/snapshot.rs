@@ -0,0 +1,182 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{
+    evolution::{blob::RGB, development::Encoding, gene::Genome, square::Symmetry},
+    simulation::islands::Archipelago,
+};
+
+/// The first line of every snapshot file
+const HEADER: &str = "evolution snapshot 1";
+
+/// The genomes of an island as they are saved, cost functions and settings are not part of a snapshot
+#[derive(Debug, Clone)]
+pub struct SavedIsland {
+    pub generation: usize,
+    pub encoding: Encoding,
+    pub genomes: Vec<Genome<RGB>>,
+}
+
+/// A plain text file, one line per island followed by one line per genome:
+/// island 0 generation 120 encoding automaton 12 12 6
+/// genome 12 12 bilateral ff0000ff0000...
+/// Genomes only hold their stored genes as hex colors, see Genome::symmetric
+pub fn write<P: AsRef<Path>>(path: P, archipelago: &Archipelago) -> io::Result<()> {
+    let mut content = format!("{}\n", HEADER);
+    for island in archipelago.islands.iter() {
+        let encoding = match island.encoding {
+            Encoding::Direct => "direct".to_string(),
+            Encoding::Automaton { width, height, steps } => format!("automaton {} {} {}", width, height, steps),
+        };
+        writeln!(content, "island {} generation {} encoding {}", island.island, island.generation, encoding).unwrap();
+        for blob in island.population.iter() {
+            let genome = &blob.genome;
+            let (width, height) = genome.dimensions();
+            write!(content, "genome {} {} {} ", width, height, genome.symmetry().name()).unwrap();
+            for gene in genome.iter() {
+                write!(content, "{:02x}{:02x}{:02x}", gene.r, gene.g, gene.b).unwrap();
+            }
+            content.push('\n');
+        }
+    }
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<SavedIsland>> {
+    let content = fs::read_to_string(path)?;
+    let mut lines = content.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some(HEADER) {
+        return Err(invalid(1, "not an evolution snapshot"));
+    }
+    let mut islands: Vec<SavedIsland> = Vec::new();
+    for (number, line) in lines {
+        let number = number + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["island", _, "generation", generation, "encoding", encoding @ ..] => {
+                let generation = generation.parse().map_err(|_| invalid(number, "bad generation"))?;
+                let encoding = match encoding {
+                    ["direct"] => Encoding::Direct,
+                    ["automaton", width, height, steps] => Encoding::Automaton {
+                        width: parse(width, number)?,
+                        height: parse(height, number)?,
+                        steps: parse(steps, number)?,
+                    },
+                    _ => return Err(invalid(number, "unknown encoding")),
+                };
+                if let Encoding::Automaton { width: 0, .. } | Encoding::Automaton { height: 0, .. } = encoding {
+                    return Err(invalid(number, "an automaton needs a grid to grow"));
+                }
+                islands.push(SavedIsland { generation, encoding, genomes: Vec::new() });
+            }
+            ["genome", width, height, symmetry, genes] => {
+                let island = islands.last_mut().ok_or_else(|| invalid(number, "genome before the first island"))?;
+                let (width, height): (usize, usize) = (parse(width, number)?, parse(height, number)?);
+                let symmetry = Symmetry::ALL
+                    .into_iter()
+                    .find(|s| s.name() == *symmetry)
+                    .ok_or_else(|| invalid(number, "unknown symmetry"))?;
+                let genes = genes
+                    .as_bytes()
+                    .chunks(6)
+                    .map(|hex| {
+                        let channel = |i: usize| {
+                            std::str::from_utf8(hex.get(i..i + 2)?).ok().and_then(|c| u8::from_str_radix(c, 16).ok())
+                        };
+                        Some(RGB { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+                    })
+                    .collect::<Option<Vec<RGB>>>()
+                    .ok_or_else(|| invalid(number, "genes are not hex colors"))?;
+                if width == 0 || height == 0 || genes.len() != symmetry.domain_size(width, height) {
+                    return Err(invalid(number, "the amount of genes does not match the dimensions"));
+                }
+                island.genomes.push(Genome::symmetric(genes, width, height, symmetry));
+            }
+            _ => return Err(invalid(number, "expected an island or a genome")),
+        }
+    }
+    if islands.is_empty() || islands.iter().any(|island| island.genomes.is_empty()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "every island of a snapshot needs a genome"));
+    }
+    Ok(islands)
+}
+
+fn parse(word: &str, line: usize) -> io::Result<usize> {
+    word.parse().map_err(|_| invalid(line, "expected a whole number"))
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("snapshot line {}: {}", line, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evolution::builder::{GenomeBuilder, Initializer, PopulationBuilder},
+        simulation::{controller::ControllerBuilder, islands::Migration},
+    };
+
+    fn island(symmetry: Symmetry, encoding: Encoding) -> ControllerBuilder {
+        let population = PopulationBuilder::new()
+            .size(6)
+            .genome(GenomeBuilder::new().dimensions(7, 5).symmetry(symmetry))
+            .initializer(Initializer::Random);
+        ControllerBuilder::new().population(population).encoding(encoding)
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("evolution_{}_{}.txt", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trip_keeps_every_island() {
+        let islands = [
+            island(Symmetry::None, Encoding::Direct),
+            island(Symmetry::Bilateral, Encoding::Direct),
+            island(Symmetry::FourFold, Encoding::automaton(12, 10)),
+            island(Symmetry::EightFold, Encoding::Direct),
+        ];
+        let mut archipelago = Archipelago::new(
+            islands.iter().map(|island| island.build().unwrap()).collect(),
+            Migration::default(),
+        );
+        for (i, island) in archipelago.islands.iter_mut().enumerate() {
+            island.generation = 10 * i + 3;
+        }
+        let path = path("round_trip");
+        write(&path, &archipelago).unwrap();
+        let saved = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved.len(), archipelago.islands.len());
+        for (saved, island) in saved.iter().zip(archipelago.islands.iter()) {
+            assert_eq!(saved.generation, island.generation);
+            assert_eq!(saved.encoding, island.encoding);
+            assert_eq!(saved.genomes.len(), island.population.len());
+            for (genome, blob) in saved.genomes.iter().zip(island.population.iter()) {
+                assert_eq!(genome.dimensions(), blob.genome.dimensions());
+                assert_eq!(genome.symmetry(), blob.genome.symmetry());
+                assert!(genome.iter().eq(blob.genome.iter()));
+            }
+        }
+        assert!(matches!(saved[2].encoding, Encoding::Automaton { width: 12, height: 10, .. }));
+        assert_eq!(saved[1].genomes[0].symmetry(), Symmetry::Bilateral);
+    }
+
+    #[test]
+    fn rejects_genes_which_do_not_fit_the_dimensions() {
+        let path = path("mismatch");
+        fs::write(&path, format!("{}\nisland 0 generation 0 encoding direct\ngenome 2 2 bilateral ff0000\n", HEADER))
+            .unwrap();
+        let result = read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
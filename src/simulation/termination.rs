@@ -0,0 +1,177 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{
+    evolution::{blob::RGB, gene::Genome},
+    simulation::islands::Archipelago,
+    statistics::metrics::{Diversity, GenerationMetrics},
+};
+
+/// The reason a run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criterion {
+    Generations,
+    Fitness,
+    Time,
+    Stagnation,
+    DiversityCollapse,
+}
+
+impl Criterion {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Criterion::Generations => "generation limit",
+            Criterion::Fitness => "target fitness",
+            Criterion::Time => "time budget",
+            Criterion::Stagnation => "stagnation",
+            Criterion::DiversityCollapse => "diversity collapse",
+        }
+    }
+}
+
+/// Stopping conditions of a run, every criterion is optional
+/// The first criterion which is met ends the run
+#[derive(Debug, Clone)]
+pub struct Termination {
+    /// generations since the start of the run
+    pub generations: Option<usize>,
+    /// stop once any island reaches this cost
+    pub target_cost: Option<f32>,
+    pub budget: Option<Duration>,
+    /// stop after this many generations without a new best cost
+    pub stagnation: Option<usize>,
+    /// stop once the mean pairwise similarity of every island exceeds this
+    pub collapse: Option<f32>,
+    start: Instant,
+    start_generation: usize,
+    best: f32,
+    improved: usize,
+}
+
+impl Default for Termination {
+    fn default() -> Self {
+        Termination {
+            generations: None,
+            target_cost: None,
+            budget: None,
+            stagnation: None,
+            collapse: None,
+            start: Instant::now(),
+            start_generation: 0,
+            best: f32::MAX,
+            improved: 0,
+        }
+    }
+}
+
+impl Termination {
+    /// Starts counting generations, time and stagnation from the current state
+    pub fn restart(&mut self, archipelago: &Archipelago) {
+        self.start = Instant::now();
+        self.start_generation = archipelago.generation();
+        self.improved = archipelago.generation();
+        self.best = f32::MAX;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Call once per generation, returns the outcome if the run is over
+    pub fn check(&mut self, archipelago: &Archipelago) -> Option<RunOutcome> {
+        let generation = archipelago.generation();
+        let (_, _, best) = best_of(archipelago);
+        // a restored generation may lie before the last improvement
+        self.improved = self.improved.min(generation);
+        if best < self.best {
+            self.best = best;
+            self.improved = generation;
+        }
+
+        let criterion = if self.generations.map_or(false, |n| generation.saturating_sub(self.start_generation) >= n) {
+            Criterion::Generations
+        } else if self.target_cost.map_or(false, |target| best <= target) {
+            Criterion::Fitness
+        } else if self.budget.map_or(false, |budget| self.elapsed() >= budget) {
+            Criterion::Time
+        } else if self.stagnation.map_or(false, |n| generation - self.improved >= n) {
+            Criterion::Stagnation
+        } else if self.collapse.map_or(false, |limit| {
+            archipelago
+                .islands
+                .iter()
+                .all(|island| Diversity::measure(&island.population).similarity >= limit)
+        }) {
+            Criterion::DiversityCollapse
+        } else {
+            return None;
+        };
+        Some(RunOutcome::new(criterion, archipelago, self))
+    }
+}
+
+/// The island, grown grid and cost of the best creature of all islands
+fn best_of(archipelago: &Archipelago) -> (usize, Genome<RGB>, f32) {
+    archipelago
+        .islands
+        .iter()
+        .filter_map(|island| {
+            let genome = island.best(1).pop()?;
+            let cost = (island.genome_cost())(&genome);
+            let grown = island.encoding.develop(&genome).unwrap_or(genome);
+            Some((island.island, grown, cost))
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+        .expect("An archipelago has at least one creature")
+}
+
+/// How and why a run ended
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub criterion: Criterion,
+    pub generations: usize,
+    pub elapsed: Duration,
+    /// the grid of the best creature of all islands and where it lives
+    pub best: Genome<RGB>,
+    pub best_cost: f32,
+    pub best_island: usize,
+    /// the statistics of every island during the last generation
+    pub metrics: Vec<GenerationMetrics>,
+}
+
+impl RunOutcome {
+    fn new(criterion: Criterion, archipelago: &Archipelago, termination: &Termination) -> Self {
+        let (best_island, best, best_cost) = best_of(archipelago);
+        RunOutcome {
+            criterion,
+            generations: archipelago.generation().saturating_sub(termination.start_generation),
+            elapsed: termination.elapsed(),
+            best,
+            best_cost,
+            best_island,
+            metrics: archipelago.islands.iter().map(|island| island.metrics()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stopped by {} after {} generations in {:.1}s, best cost {:.4} on island {}",
+            self.criterion.name(),
+            self.generations,
+            self.elapsed.as_secs_f32(),
+            self.best_cost,
+            self.best_island,
+        )?;
+        for metrics in self.metrics.iter() {
+            write!(
+                f,
+                "\n  island {}: median {:.4}, similarity {:.3}, {} clusters",
+                metrics.island, metrics.median, metrics.similarity, metrics.clusters
+            )?;
+        }
+        Ok(())
+    }
+}
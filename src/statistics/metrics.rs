@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::evolution::{
+    blob::RGB,
+    diversity::{clusters, gene_entropy, mean_pairwise_similarity, similarity_matrix},
+    gene::{CostFunction, Genome},
+    population::SimpleBlobPopulation,
+};
+
+/// Genomes at least this similar belong to the same cluster
+pub const CLUSTER_THRESHOLD: f32 = 0.9;
+
+/// How different the genomes of a population are
+#[derive(Debug, Clone, Default)]
+pub struct Diversity {
+    pub similarity: f32,
+    /// normalized entropy of every gene position
+    pub entropy: Vec<f32>,
+    pub clusters: usize,
+}
+
+impl Diversity {
+    pub fn measure(population: &SimpleBlobPopulation) -> Self {
+        let genomes: Vec<&Genome<RGB>> = population.iter().map(|b| &b.genome).collect();
+        let matrix = similarity_matrix(&genomes);
+        Diversity {
+            similarity: mean_pairwise_similarity(&matrix),
+            entropy: gene_entropy(&genomes, RGB::BUCKETS, RGB::bucket),
+            clusters: clusters(&matrix, CLUSTER_THRESHOLD),
+        }
+    }
+
+    pub fn mean_entropy(&self) -> f32 {
+        self.entropy.iter().sum::<f32>() / self.entropy.len().max(1) as f32
+    }
+}
+
+/// Statistics of a single generation
+#[derive(Debug, Clone, Default)]
+pub struct GenerationMetrics {
+    pub island: usize,
+    pub generation: usize,
+    /// cost quantiles, lower is better
+    pub best: f32,
+    pub q25: f32,
+    pub median: f32,
+    pub q75: f32,
+    pub worst: f32,
+    pub mean: f32,
+    /// mean pairwise similarity of the genomes in [0, 1]
+    pub similarity: f32,
+    /// mean normalized entropy of the gene positions
+    pub entropy: f32,
+    pub clusters: usize,
+    /// mutations per gene during the last generation
+    pub mutation_rate: f32,
+    pub duration: Duration,
+}
+
+impl GenerationMetrics {
+    const FIELDS: [&'static str; 13] = [
+        "island", "generation", "best", "q25", "median", "q75", "worst", "mean", "similarity", "entropy", "clusters",
+        "mutation_rate", "duration_ms",
+    ];
+
+    pub fn measure(
+        population: &SimpleBlobPopulation,
+        cost_function: &CostFunction<Genome<RGB>>,
+        generation: usize,
+        mutations: usize,
+        duration: Duration,
+    ) -> Self {
+        let mut costs: Vec<f32> = population.iter().map(|b| cost_function(&b.genome)).collect();
+        costs.sort_unstable_by(|a, b| a.total_cmp(b));
+        let genes = population.iter().map(|b| b.genome.len()).sum::<usize>().max(1);
+        let diversity = Diversity::measure(population);
+
+        GenerationMetrics {
+            island: 0,
+            generation,
+            best: quantile(&costs, 0.0),
+            q25: quantile(&costs, 0.25),
+            median: quantile(&costs, 0.5),
+            q75: quantile(&costs, 0.75),
+            worst: quantile(&costs, 1.0),
+            mean: costs.iter().sum::<f32>() / costs.len().max(1) as f32,
+            similarity: diversity.similarity,
+            entropy: diversity.mean_entropy(),
+            clusters: diversity.clusters,
+            mutation_rate: mutations as f32 / genes as f32,
+            duration,
+        }
+    }
+
+    fn values(&self) -> [String; 13] {
+        [
+            self.island.to_string(),
+            self.generation.to_string(),
+            self.best.to_string(),
+            self.q25.to_string(),
+            self.median.to_string(),
+            self.q75.to_string(),
+            self.worst.to_string(),
+            self.mean.to_string(),
+            self.similarity.to_string(),
+            self.entropy.to_string(),
+            self.clusters.to_string(),
+            self.mutation_rate.to_string(),
+            (self.duration.as_secs_f64() * 1000.0).to_string(),
+        ]
+    }
+}
+
+/// Linear interpolated quantile of sorted values
+fn quantile(sorted: &[f32], q: f32) -> f32 {
+    if sorted.is_empty() {
+        return f32::NAN;
+    }
+    let position = q * (sorted.len() - 1) as f32;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    let t = position - lower as f32;
+    sorted[lower] * (1.0 - t) + sorted[upper] * t
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    Csv,
+    /// one JSON object per line
+    Json,
+}
+
+impl MetricsFormat {
+    /// .json and .jsonl files get JSON lines, everything else CSV
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("json") | Some("jsonl") => MetricsFormat::Json,
+            _ => MetricsFormat::Csv,
+        }
+    }
+}
+
+/// Appends GenerationMetrics to a file
+pub struct MetricsSink {
+    writer: BufWriter<File>,
+    format: MetricsFormat,
+}
+
+impl MetricsSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let format = MetricsFormat::from_path(&path);
+        let mut writer = BufWriter::new(File::create(path)?);
+        if format == MetricsFormat::Csv {
+            writeln!(writer, "{}", GenerationMetrics::FIELDS.join(","))?;
+        }
+        Ok(MetricsSink { writer, format })
+    }
+
+    pub fn record(&mut self, metrics: &GenerationMetrics) -> io::Result<()> {
+        let values = metrics.values();
+        match self.format {
+            MetricsFormat::Csv => writeln!(self.writer, "{}", values.join(",")),
+            MetricsFormat::Json => {
+                let fields: Vec<String> = GenerationMetrics::FIELDS
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(name, value)| format!("\"{}\":{}", name, json_number(value)))
+                    .collect();
+                writeln!(self.writer, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// JSON has no NaN or infinity
+fn json_number(value: &str) -> &str {
+    match value {
+        "NaN" | "inf" | "-inf" => "null",
+        _ => value,
+    }
+}
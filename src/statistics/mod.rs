@@ -1,2 +1,3 @@
-pub mod function;
+pub mod metrics;
 pub mod plot;
+pub mod provenance;
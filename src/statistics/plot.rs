@@ -1,4 +1,4 @@
-use crate::{Model, Nannou};
+use crate::{Nannou, View};
 use nannou::geom::{pt2, Rect};
 use nannou::{color, Draw};
 
@@ -8,7 +8,7 @@ pub struct Plot {
 }
 
 impl Nannou for Plot {
-    fn draw(&self, draw: &Draw, _model: &Model) {
+    fn draw(&self, draw: &Draw, _view: &View) {
         let frame = self.nannou_frame;
 
         // background of plot
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::evolution::{
+    gene::{Birth, Provenance},
+    population::SimpleBlobPopulation,
+};
+
+/// Counts per grid position where the genes of the children came from
+/// Positions which are mostly inherited from the mother are stable, the others churn
+/// Positions are cells of the grid, so genomes of different sizes count the same cell alike
+#[derive(Debug, Clone, Default)]
+pub struct ProvenanceMap {
+    /// [mother, father, mutated] for every (x, y) a child had
+    counts: BTreeMap<(usize, usize), [u32; 3]>,
+    births: u32,
+}
+
+impl ProvenanceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.clear();
+        self.births = 0;
+    }
+
+    pub fn births(&self) -> u32 {
+        self.births
+    }
+
+    /// The children of the births have to be in their slots of the population already
+    pub fn record(&mut self, births: &[Birth], population: &SimpleBlobPopulation) {
+        for birth in births {
+            let genome = &population[birth.slot].genome;
+            // a slot which was taken again by a later child no longer holds this one
+            if birth.provenance.len() != genome.len() {
+                continue;
+            }
+            let (width, height) = genome.dimensions();
+            for y in 0..height {
+                for x in 0..width {
+                    let count = self.counts.entry((x, y)).or_insert([0; 3]);
+                    match birth.provenance[genome.gene_index(x, y)] {
+                        Provenance::Mother => count[0] += 1,
+                        Provenance::Father(_) => count[1] += 1,
+                        Provenance::Mutated => count[2] += 1,
+                    }
+                }
+            }
+            self.births += 1;
+        }
+    }
+
+    /// The share of births in which the gene at a position was not inherited from the mother
+    pub fn churn(&self) -> BTreeMap<(usize, usize), f32> {
+        let births = self.births.max(1) as f32;
+        self.counts.iter().map(|(&at, c)| (at, (c[1] + c[2]) as f32 / births)).collect()
+    }
+
+    /// One line per grid position, x and y as in Square::get
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("x,y,mother,father,mutated,churn\n");
+        let births = self.births.max(1) as f32;
+        for (&(x, y), count) in self.counts.iter() {
+            let churn = (count[1] + count[2]) as f32 / births;
+            let _ = writeln!(csv, "{},{},{},{},{},{}", x, y, count[0], count[1], count[2], churn);
+        }
+        csv
+    }
+}
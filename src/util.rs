@@ -1,101 +1,55 @@
-use nannou::geom::Point2;
-use nannou::math::num_traits::PrimInt;
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use std::f32::consts::PI;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
 
-pub trait Create {
-    type Params;
+use crate::evolution::error::EvolutionError;
 
-    fn create() -> Self;
+/// A piece of information which can be created at random, e.g. a gene
+/// Genomes, populations and controllers have builders instead
+pub trait Create: Sized {
+    fn create() -> Result<Self, EvolutionError>;
+}
 
-    fn create_like(params: Option<Self::Params>) -> Self;
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
 }
 
-/// distribute *amount* objects uniformly in space
-pub fn distribute_uniformly<T: PrimInt>(amount: T, object_size: f32) -> Vec<Point2> {
-    let mut result: Vec<Point2> = Vec::new();
-    let amount = amount.to_u32().unwrap_or(0);
-    let sparsity: f32 = 1.0;
-    let mut rng = rand::thread_rng();
-    distribute_uniformly_recursive(
-        &mut rng,
-        amount,
-        amount,
-        Point2::new(0., 0.),
-        0.,
-        sparsity * object_size,
-        sparsity * object_size,
-        &mut result,
-    );
-    result
+/// Makes every later random decision of this thread repeat for the same seed
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
 }
 
-fn distribute_uniformly_recursive(
-    rng: &mut ThreadRng,
-    amount: u32,
-    target: u32,
-    center: Point2,
-    inner_bound: f32,
-    outer_bound: f32,
-    margin_radius: f32,
-    points: &mut Vec<Point2>,
-) {
-    if target <= 0 {
-        return;
-    }
-    let randomness = 0.1;
-    // free area
-    let area_all = calc_area(outer_bound) - calc_area(inner_bound);
-    // area of a single object + padding
-    let area_single = calc_area(margin_radius);
-    // amount of objects to fit in the current space
-    let capacity: usize = (area_all / area_single) as usize;
+/// The random number generator of this thread, see seed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SharedRng;
 
-    // random offset from unit circle
-    let random_angle = rng.gen_range(0.0..2.0 * PI);
-    // step size on unit circle
-    let step_size = (2.0 * PI) / capacity as f32;
-    // center of free area
-    let initial_radius = inner_bound + margin_radius / 2.0;
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
 
-    let mut index = 0;
-    while capacity > index && points.len() < amount as usize {
-        let random_angle_offset = (randomness / 2.0) + rng.gen_range(0.0..=randomness);
-        let random_radius_offset_x = (randomness / 2.0) + rng.gen_range(0.0..=randomness / 2.);
-        let random_radius_offset_y = (randomness / 2.0) + rng.gen_range(0.0..=randomness / 2.);
-        let angle = random_angle + step_size * index as f32 + step_size * random_angle_offset;
-        let x = angle.cos();
-        let y = angle.sin();
-        let new_point = Point2::new(
-            center.x + x * initial_radius + margin_radius * random_radius_offset_x,
-            center.y + y * initial_radius + margin_radius * random_radius_offset_y,
-        );
-        points.push(new_point);
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
 
-        index += 1;
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
     }
 
-    distribute_uniformly_recursive(
-        rng,
-        amount,
-        target - index as u32,
-        center,
-        outer_bound,
-        outer_bound + margin_radius,
-        margin_radius,
-        points,
-    );
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
 }
 
-fn calc_area(radius: f32) -> f32 {
-    PI * radius * radius
+/// Use this instead of rand::thread_rng, so runs can be seeded
+pub fn rng() -> SharedRng {
+    SharedRng
 }
 
 /// returns a number x of natural numbers with probability p^x
 pub fn rnd_exp(expected: usize) -> usize {
     assert!(expected > 0);
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     // E = (1/1-p)
     // E * (1-p) = 1
     // 1 - p = 1/E